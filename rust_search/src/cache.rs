@@ -0,0 +1,356 @@
+//! HTTP caching of responses that only change when a collection is reindexed.
+//!
+//! The ETag of a response is derived from the version of the collection it is read from
+//! and the request parameters. The version is the time of the last indexing run, which the
+//! indexers store in the collection metadata (`INDEXED_AT_KEY`), of the collection itself or
+//! of the one its alias points to. It is also the `Last-Modified` time. Responses of
+//! collections without this marker are not cached, as nothing else reliably changes on every
+//! write.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use actix_web::http::header::{
+    HttpDate, ACCEPT, CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder};
+use qdrant_client::qdrant::value::Kind;
+use qdrant_client::qdrant::{GetCollectionInfoRequest, Value};
+use qdrant_client::Qdrant;
+use sha2::{Digest, Sha256};
+
+use crate::common::{env_or, INDEXED_AT_KEY};
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// How often the collection versions are checked (`CACHE_VERSION_REFRESH_SECS`)
+    pub refresh: Duration,
+    /// Added to every collection version, e.g. a deployment id (`CACHE_VERSION`)
+    pub salt: String,
+    /// `Cache-Control` of `/md/` responses (`CACHE_CONTROL_MD`)
+    pub md_cache_control: String,
+    /// `Cache-Control` of `/api/search` responses (`CACHE_CONTROL_SEARCH`)
+    pub search_cache_control: String,
+}
+
+impl CacheConfig {
+    pub fn from_env() -> Self {
+        Self {
+            refresh: Duration::from_secs(env_or("CACHE_VERSION_REFRESH_SECS", 60)),
+            salt: env_or("CACHE_VERSION", String::new()),
+            md_cache_control: env_or(
+                "CACHE_CONTROL_MD",
+                "public, max-age=86400, stale-while-revalidate=3600".to_string(),
+            ),
+            search_cache_control: env_or("CACHE_CONTROL_SEARCH", "public, max-age=300".to_string()),
+        }
+    }
+}
+
+/// Version of a collection and when it was indexed.
+type Version = (String, SystemTime);
+
+struct CollectionVersion {
+    /// `None` if the collection has no indexing time
+    version: Option<Version>,
+    checked_at: Instant,
+}
+
+pub struct HttpCache {
+    client: Qdrant,
+    pub config: CacheConfig,
+    versions: Mutex<HashMap<String, CollectionVersion>>,
+}
+
+/// Validators of a response: its ETag and when its content last changed.
+#[derive(Debug, Clone)]
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: SystemTime,
+    /// Whether equal ETags only promise equivalent content, see [`Validators::weak`]
+    pub weak: bool,
+}
+
+impl HttpCache {
+    pub fn new(client: Qdrant, config: CacheConfig) -> Self {
+        Self {
+            client,
+            config,
+            versions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn fetch_version(&self, collection: &str) -> anyhow::Result<Option<Version>> {
+        let aliases = self.client.list_aliases().await?.aliases;
+        let name = aliases
+            .iter()
+            .find(|a| a.alias_name == collection)
+            .map_or(collection, |alias| alias.collection_name.as_str());
+        let info = self
+            .client
+            .collection_info(GetCollectionInfoRequest {
+                collection_name: name.to_string(),
+            })
+            .await?
+            .result
+            .unwrap_or_default();
+        let metadata = info.config.map(|c| c.metadata).unwrap_or_default();
+        Ok(indexed_version(name, &metadata, &self.config.salt))
+    }
+
+    /// Version of `collection`, checked at most once per refresh interval.
+    async fn version(&self, collection: &str) -> anyhow::Result<Option<Version>> {
+        {
+            let versions = self.versions.lock().unwrap();
+            if let Some(cached) = versions.get(collection) {
+                if cached.checked_at.elapsed() < self.config.refresh {
+                    return Ok(cached.version.clone());
+                }
+            }
+        }
+        let version = self.fetch_version(collection).await?;
+        if version.is_none() {
+            log::warn!(
+                "{} has no `{}` metadata, its responses are not cached",
+                collection,
+                INDEXED_AT_KEY
+            );
+        }
+        self.versions.lock().unwrap().insert(
+            collection.to_string(),
+            CollectionVersion {
+                version: version.clone(),
+                checked_at: Instant::now(),
+            },
+        );
+        Ok(version)
+    }
+
+    /// Validators of a response to `req` served from `collection`.
+    ///
+    /// Returns `None` if the collection version can't be determined, so that the response
+    /// is not cached.
+    pub async fn validators(&self, collection: &str, req: &HttpRequest) -> Option<Validators> {
+        match self.version(collection).await {
+            Ok(version) => version.map(|(version, indexed_at)| Validators {
+                etag: etag(&version, req),
+                last_modified: indexed_at,
+                weak: false,
+            }),
+            Err(e) => {
+                log::warn!("Failed to get the version of {}: {}", collection, e);
+                None
+            }
+        }
+    }
+}
+
+/// Version of the collection `name` from the indexing time in its `metadata`.
+fn indexed_version(name: &str, metadata: &HashMap<String, Value>, salt: &str) -> Option<Version> {
+    let secs = match metadata.get(INDEXED_AT_KEY)?.kind.as_ref()? {
+        Kind::IntegerValue(secs) => u64::try_from(*secs).ok()?,
+        Kind::DoubleValue(secs) => *secs as u64,
+        _ => return None,
+    };
+    Some((
+        format!("{name}:{secs}{salt}"),
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+    ))
+}
+
+/// Strong ETag of the collection version and the parameters of `req`.
+///
+/// `Accept` is included as it selects the format of `/md/` responses.
+fn etag(version: &str, req: &HttpRequest) -> String {
+    let accept = req
+        .headers()
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    quoted_digest(&[version, req.path(), req.query_string(), accept])
+}
+
+fn quoted_digest(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    let digest = hasher.finalize();
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+impl Validators {
+    /// Validators of a response that also depends on another input, e.g. a dictionary loaded
+    /// by the service, identified by `version` and last changed at `modified`.
+    pub fn depending_on(self, version: &str, modified: Option<SystemTime>) -> Self {
+        Self {
+            etag: quoted_digest(&[&self.etag, version]),
+            last_modified: modified.map_or(self.last_modified, |m| m.max(self.last_modified)),
+            weak: self.weak,
+        }
+    }
+
+    /// Validators of a response whose body can differ between equivalent responses, e.g.
+    /// by a timing field, so that its ETag is sent as a weak one.
+    pub fn weak(self) -> Self {
+        Self { weak: true, ..self }
+    }
+
+    /// The ETag header value.
+    fn etag_header(&self) -> String {
+        if self.weak {
+            format!("W/{}", self.etag)
+        } else {
+            self.etag.clone()
+        }
+    }
+
+    /// Whether the client's copy is still current, according to `If-None-Match` or,
+    /// without it, `If-Modified-Since`. ETags are compared weakly, as `If-None-Match` requires.
+    pub fn is_fresh(&self, req: &HttpRequest) -> bool {
+        if let Some(if_none_match) = req.headers().get(IF_NONE_MATCH) {
+            let Ok(if_none_match) = if_none_match.to_str() else {
+                return false;
+            };
+            return if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.etag);
+        }
+        req.headers()
+            .get(IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<HttpDate>().ok())
+            .is_some_and(|since| {
+                // HTTP dates have a resolution of seconds
+                let secs = |time: SystemTime| {
+                    time.duration_since(SystemTime::UNIX_EPOCH)
+                        .map_or(0, |d| d.as_secs())
+                };
+                secs(self.last_modified) <= secs(since.into())
+            })
+    }
+
+    /// Add the validators and `cache_control` to a response.
+    pub fn apply(&self, builder: &mut HttpResponseBuilder, cache_control: &str) {
+        builder
+            .insert_header((ETAG, self.etag_header()))
+            .insert_header((LAST_MODIFIED, HttpDate::from(self.last_modified)))
+            .insert_header((CACHE_CONTROL, cache_control.to_string()));
+    }
+
+    pub fn not_modified(&self, cache_control: &str) -> HttpResponse {
+        let mut builder = HttpResponse::NotModified();
+        self.apply(&mut builder, cache_control);
+        builder.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn validators() -> Validators {
+        Validators {
+            etag: "\"abc\"".to_string(),
+            last_modified: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            weak: false,
+        }
+    }
+
+    #[test]
+    fn etag_depends_on_version_and_params() {
+        let req = TestRequest::with_uri("/md/documentation?s=install").to_http_request();
+        let other = TestRequest::with_uri("/md/documentation?s=faq").to_http_request();
+        let json = TestRequest::with_uri("/md/documentation?s=install")
+            .insert_header((ACCEPT, "application/json"))
+            .to_http_request();
+        assert_eq!(etag("v1", &req), etag("v1", &req));
+        assert_ne!(etag("v1", &req), etag("v2", &req));
+        assert_ne!(etag("v1", &req), etag("v1", &other));
+        assert_ne!(etag("v1", &req), etag("v1", &json));
+    }
+
+    #[test]
+    fn other_inputs_change_the_validators() {
+        let dictionary_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let reloaded = validators().depending_on("v2", Some(dictionary_modified));
+        assert_ne!(reloaded.etag, validators().depending_on("v1", None).etag);
+        assert_eq!(reloaded.last_modified, dictionary_modified);
+        // an older input doesn't move the indexing time back
+        let older = validators().depending_on("v1", Some(SystemTime::UNIX_EPOCH));
+        assert_eq!(older.last_modified, validators().last_modified);
+    }
+
+    #[test]
+    fn version_is_the_indexing_time() {
+        let metadata = HashMap::from([(INDEXED_AT_KEY.to_string(), Value::from(1_700_000_000))]);
+        let (version, indexed_at) = indexed_version("sections-v2", &metadata, "").unwrap();
+        assert_eq!(version, "sections-v2:1700000000");
+        assert_eq!(
+            indexed_at,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        let reindexed = HashMap::from([(INDEXED_AT_KEY.to_string(), Value::from(1_700_000_600))]);
+        assert_ne!(
+            indexed_version("sections-v2", &reindexed, "").unwrap().0,
+            version
+        );
+        assert_eq!(indexed_version("sections-v2", &HashMap::new(), ""), None);
+    }
+
+    #[test]
+    fn if_none_match() {
+        let fresh = TestRequest::default()
+            .insert_header((IF_NONE_MATCH, "\"xyz\", W/\"abc\""))
+            .to_http_request();
+        assert!(validators().is_fresh(&fresh));
+        let stale = TestRequest::default()
+            .insert_header((IF_NONE_MATCH, "\"xyz\""))
+            .to_http_request();
+        assert!(!validators().is_fresh(&stale));
+        assert!(!validators().is_fresh(&TestRequest::default().to_http_request()));
+        let weak = validators().weak();
+        assert!(weak.is_fresh(&fresh));
+        let strong = TestRequest::default()
+            .insert_header((IF_NONE_MATCH, "\"abc\""))
+            .to_http_request();
+        assert!(weak.is_fresh(&strong));
+    }
+
+    #[test]
+    fn if_modified_since() {
+        let since = |secs: u64| {
+            let date = HttpDate::from(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+            TestRequest::default()
+                .insert_header((IF_MODIFIED_SINCE, date))
+                .to_http_request()
+        };
+        assert!(validators().is_fresh(&since(1_700_000_000)));
+        assert!(validators().is_fresh(&since(1_700_000_100)));
+        assert!(!validators().is_fresh(&since(1_600_000_000)));
+    }
+
+    #[test]
+    fn not_modified_keeps_validators() {
+        let response = validators().not_modified("public, max-age=60");
+        assert_eq!(response.status(), actix_web::http::StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(ETAG).unwrap(), "\"abc\"");
+        assert_eq!(
+            response.headers().get(CACHE_CONTROL).unwrap(),
+            "public, max-age=60"
+        );
+    }
+
+    #[test]
+    fn weak_etags_are_marked() {
+        let response = validators().weak().not_modified("no-cache");
+        assert_eq!(response.headers().get(ETAG).unwrap(), "W/\"abc\"");
+        let weak = validators().depending_on("v2", None).weak();
+        assert_eq!(weak.etag_header(), format!("W/{}", weak.etag));
+    }
+}
@@ -1,16 +1,49 @@
 // Allow unused code, as not all submodules use all functions
 #![allow(dead_code)]
 
-use ndarray::{Array, Axis, CowArray};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, Context};
+use futures::StreamExt;
+use ndarray::{s, Array, Array2, ArrayView3, Axis, CowArray, Ix3};
 use ort::tensor::OrtOwnedTensor;
 use ort::Session;
 use ort::Value as OrtValue;
+use qdrant_client::qdrant::{
+    PointId, PointStruct, UpdateCollectionBuilder, UpsertPointsBuilder, Value, Vectors,
+};
+use qdrant_client::Qdrant;
 
 use rust_tokenizers::tokenizer::{BertTokenizer, Tokenizer, TruncationStrategy};
+use rust_tokenizers::vocab::Vocab;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 pub const COLLECTION_NAME: &str = "site";
 pub const PREFIX_COLLECTION_NAME: &str = "prefix-cache";
+pub const SECTION_COLLECTION_NAME: &str = "sections";
+pub const SKILLS_COLLECTION_NAME: &str = "skills";
+pub const SNIPPET_COLLECTION_NAME: &str = "snippet-search";
+pub const SITE_DATA: &str = "../page-search/data/abstracts.jsonl";
 pub const MODEL_PATH: &str = "all-MiniLM-L6-v2.onnx";
+pub const MAX_TOKENS: usize = 512;
+/// Collection metadata with the unix time of the last indexing run, versions the HTTP caches
+pub const INDEXED_AT_KEY: &str = "indexed_at";
+
+/// Record the time of this indexing run in the metadata of `collection`.
+pub async fn mark_indexed(client: &Qdrant, collection: &str) -> anyhow::Result<()> {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+    let metadata = HashMap::from([(INDEXED_AT_KEY.to_string(), serde_json::json!(now))]);
+    client
+        .update_collection(UpdateCollectionBuilder::new(collection).metadata(metadata))
+        .await?;
+    Ok(())
+}
 
 pub fn get_qdrant_url() -> String {
     match std::env::var("QDRANT_URL") {
@@ -22,25 +55,693 @@ pub fn get_qdrant_url() -> String {
     }
 }
 
+pub fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Longest prefix in bytes with a point id in `prefix-cache`, see [`prefix_to_num`].
+pub const PREFIX_ID_MAX_BYTES: usize = 8;
+
+/// The point id of `prefix` in `prefix-cache`: its zero padded bytes as a little endian
+/// number. Longer prefixes than `PREFIX_ID_MAX_BYTES` have none, as it wouldn't be unique.
+pub fn prefix_to_num(prefix: &str) -> Option<u64> {
+    let bytes = prefix.as_bytes();
+    if bytes.len() > PREFIX_ID_MAX_BYTES {
+        return None;
+    }
+    let mut result = [0_u8; PREFIX_ID_MAX_BYTES];
+    result[..bytes.len()].copy_from_slice(bytes);
+    Some(u64::from_le_bytes(result))
+}
+
+pub fn prefix_to_id(prefix: &str) -> Option<PointId> {
+    prefix_to_num(prefix).map(PointId::from)
+}
+
 pub fn get_embedding(tokenizer: &BertTokenizer, session: &Session, query: &str) -> Vec<f32> {
+    get_embeddings(tokenizer, session, &[query])
+        .expect("Failed to embed query")
+        .pop()
+        .unwrap()
+}
+
+/// Embed a batch of texts in a single model run.
+///
+/// Shorter inputs are padded to the longest one in the batch, and the mean pooling
+/// only covers the real tokens, so the result matches embedding each text on its own.
+pub fn get_embeddings(
+    tokenizer: &BertTokenizer,
+    session: &Session,
+    texts: &[&str],
+) -> anyhow::Result<Vec<Vec<f32>>> {
+    if texts.is_empty() {
+        return Ok(vec![]);
+    }
     // tokenize
-    let mut encoding = tokenizer.encode(query, None, 512, &TruncationStrategy::LongestFirst, 1);
-    let alloc = session.allocator();
-    let token_ids = std::mem::take(&mut encoding.token_ids);
-    let shape = (1, token_ids.len());
-    let token_ids = Array::from_shape_vec(shape, token_ids).unwrap();
-    let attentions = Array::from_elem(shape, 1_i64);
-    let type_ids = Array::from_elem(shape, 0_i64);
+    let encodings: Vec<Vec<i64>> = texts
+        .iter()
+        .map(|text| {
+            tokenizer
+                .encode(text, None, MAX_TOKENS, &TruncationStrategy::LongestFirst, 1)
+                .token_ids
+        })
+        .collect();
+    let pad_id = tokenizer
+        .vocab()
+        .token_to_id(tokenizer.vocab().get_pad_value());
+    let (token_ids, attentions) = padded_batch(&encodings, pad_id);
+    let type_ids = Array::from_elem(token_ids.dim(), 0_i64);
     // embed
-    let output: OrtOwnedTensor<f32, _> = session
-        .run(vec![
-            OrtValue::from_array(alloc, &CowArray::from(token_ids.into_dyn())).unwrap(),
-            OrtValue::from_array(alloc, &CowArray::from(attentions.into_dyn())).unwrap(),
-            OrtValue::from_array(alloc, &CowArray::from(type_ids.into_dyn())).unwrap(),
-        ])
-        .unwrap()[0]
-        .try_extract()
+    let alloc = session.allocator();
+    let token_ids = CowArray::from(token_ids.into_dyn());
+    let attentions = CowArray::from(attentions.into_dyn());
+    let type_ids = CowArray::from(type_ids.into_dyn());
+    let outputs = session.run(vec![
+        OrtValue::from_array(alloc, &token_ids)?,
+        OrtValue::from_array(alloc, &attentions)?,
+        OrtValue::from_array(alloc, &type_ids)?,
+    ])?;
+    let output: OrtOwnedTensor<f32, _> = outputs[0].try_extract()?;
+    let view = output.view();
+    let output = view.view().into_dimensionality::<Ix3>()?;
+    mean_pool(output, &encodings)
+}
+
+/// Token ids and attention mask of `encodings`, padded with `pad_id` to the longest one.
+fn padded_batch(encodings: &[Vec<i64>], pad_id: i64) -> (Array2<i64>, Array2<i64>) {
+    let max_len = encodings.iter().map(Vec::len).max().unwrap_or(0);
+    let shape = (encodings.len(), max_len);
+    let mut token_ids = Array2::from_elem(shape, pad_id);
+    let mut attentions = Array2::zeros(shape);
+    for (row, ids) in encodings.iter().enumerate() {
+        for (col, id) in ids.iter().enumerate() {
+            token_ids[[row, col]] = *id;
+            attentions[[row, col]] = 1_i64;
+        }
+    }
+    (token_ids, attentions)
+}
+
+/// Mean pooling of the token embeddings `output` over the unpadded tokens of every row.
+fn mean_pool(output: ArrayView3<f32>, encodings: &[Vec<i64>]) -> anyhow::Result<Vec<Vec<f32>>> {
+    encodings
+        .iter()
+        .enumerate()
+        .map(|(row, ids)| {
+            output
+                .slice(s![row, ..ids.len(), ..])
+                .mean_axis(Axis(0))
+                .map(|pooled| pooled.to_vec())
+                .ok_or_else(|| anyhow!("Empty token sequence in row {row}"))
+        })
+        .collect()
+}
+
+/// A single point to be embedded and stored by the indexing pipeline.
+pub struct EmbedRecord {
+    pub id: PointId,
+    pub text: String,
+    pub payload: HashMap<String, Value>,
+}
+
+/// Tuning knobs of the indexing pipeline, read from environment variables.
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    /// Number of texts run through the model at once (`EMBED_BATCH_SIZE`)
+    pub embed_batch_size: usize,
+    /// Number of embedding threads (`EMBED_THREADS`)
+    pub threads: usize,
+    /// Number of points per upsert request (`UPSERT_BATCH_SIZE`)
+    pub upsert_batch_size: usize,
+    /// Maximum number of upsert requests in flight (`UPSERT_CONCURRENCY`)
+    pub upsert_concurrency: usize,
+    /// Number of retries of a failed upsert request (`UPSERT_RETRIES`)
+    pub max_retries: u32,
+    /// How often the progress is reported
+    pub report_interval: Duration,
+}
+
+impl PipelineConfig {
+    pub fn from_env() -> Self {
+        let default_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            embed_batch_size: env_or("EMBED_BATCH_SIZE", 32_usize).max(1),
+            threads: env_or("EMBED_THREADS", default_threads).max(1),
+            upsert_batch_size: env_or("UPSERT_BATCH_SIZE", 256_usize).max(1),
+            upsert_concurrency: env_or("UPSERT_CONCURRENCY", 4_usize).max(1),
+            max_retries: env_or("UPSERT_RETRIES", 5),
+            report_interval: Duration::from_secs(env_or("PROGRESS_INTERVAL_SECS", 5)),
+        }
+    }
+}
+
+/// Periodically logs throughput and, if the total is known, the remaining time.
+pub struct Progress {
+    label: &'static str,
+    total: Option<usize>,
+    done: usize,
+    start: Instant,
+    last_report: Instant,
+    interval: Duration,
+}
+
+impl Progress {
+    pub fn new(label: &'static str, total: Option<usize>, interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            label,
+            total,
+            done: 0,
+            start: now,
+            last_report: now,
+            interval,
+        }
+    }
+
+    pub fn advance(&mut self, n: usize) {
+        self.done += n;
+        if self.last_report.elapsed() >= self.interval {
+            self.last_report = Instant::now();
+            log::info!("{}", self.status());
+        }
+    }
+
+    pub fn finish(&self) {
+        log::info!("{} (done)", self.status());
+    }
+
+    fn rate(&self) -> f64 {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.done as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    fn status(&self) -> String {
+        let rate = self.rate();
+        match self.total {
+            Some(total) => {
+                let eta = if rate > 0.0 {
+                    format_duration(total.saturating_sub(self.done) as f64 / rate)
+                } else {
+                    "?".to_string()
+                };
+                format!(
+                    "{}/{} {}, {:.1}/s, ETA {}",
+                    self.done, total, self.label, rate, eta
+                )
+            }
+            None => format!("{} {}, {:.1}/s", self.done, self.label, rate),
+        }
+    }
+}
+
+fn format_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Upsert points, retrying failed requests with exponential backoff.
+pub async fn upsert_with_retry(
+    client: &Qdrant,
+    collection: &str,
+    points: Vec<PointStruct>,
+    max_retries: u32,
+) -> anyhow::Result<usize> {
+    let count = points.len();
+    let mut backoff = Duration::from_millis(500);
+    let mut attempt = 0;
+    loop {
+        let request = UpsertPointsBuilder::new(collection, points.clone()).wait(true);
+        match client.upsert_points(request).await {
+            Ok(_) => return Ok(count),
+            Err(err) if attempt < max_retries => {
+                attempt += 1;
+                log::warn!(
+                    "Upsert of {count} points failed (attempt {attempt}/{max_retries}): {err}, retrying in {backoff:?}"
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(30));
+            }
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("Upsert of {count} points failed after {max_retries} retries")
+                })
+            }
+        }
+    }
+}
+
+fn embed_batch(
+    tokenizer: &BertTokenizer,
+    session: &Session,
+    records: Vec<EmbedRecord>,
+    embed_batch_size: usize,
+) -> anyhow::Result<Vec<PointStruct>> {
+    let mut points = Vec::with_capacity(records.len());
+    let mut records = records.into_iter().peekable();
+    while records.peek().is_some() {
+        let chunk: Vec<EmbedRecord> = records.by_ref().take(embed_batch_size).collect();
+        let texts: Vec<&str> = chunk.iter().map(|r| r.text.as_str()).collect();
+        let vectors = get_embeddings(tokenizer, session, &texts)?;
+        points.extend(chunk.into_iter().zip(vectors).map(|(record, vector)| PointStruct {
+            id: Some(record.id),
+            payload: record.payload,
+            vectors: Some(Vectors::from(vector)),
+        }));
+    }
+    Ok(points)
+}
+
+/// Hex-encoded SHA-256 of `data`, used to detect changed indexer inputs.
+pub fn hash_bytes(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CheckpointState {
+    collection: String,
+    input_hash: String,
+    batch_size: usize,
+    committed_batches: usize,
+}
+
+/// Progress of an indexing run, persisted after every committed batch.
+///
+/// A batch counts as committed once it and all batches before it are stored, so resuming
+/// skips exactly `committed_batches * batch_size` records of the same input.
+pub struct Checkpoint {
+    path: PathBuf,
+    state: CheckpointState,
+}
+
+impl Checkpoint {
+    /// Open the checkpoint at `path`.
+    ///
+    /// With `resume`, the stored progress is picked up if it belongs to the same collection
+    /// and input. Otherwise indexing starts from the first batch.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        collection: &str,
+        input_hash: &str,
+        batch_size: usize,
+        resume: bool,
+    ) -> anyhow::Result<Self> {
+        let path = path.into();
+        let fresh = CheckpointState {
+            collection: collection.to_string(),
+            input_hash: input_hash.to_string(),
+            batch_size,
+            committed_batches: 0,
+        };
+        let state = if resume && path.exists() {
+            let stored: CheckpointState = serde_json::from_slice(&std::fs::read(&path)?)
+                .with_context(|| format!("Malformed checkpoint {}", path.display()))?;
+            if stored.collection == fresh.collection && stored.input_hash == fresh.input_hash {
+                log::info!(
+                    "Resuming after {} committed batches of {}",
+                    stored.committed_batches,
+                    stored.batch_size
+                );
+                stored
+            } else {
+                log::warn!("Checkpoint {} is for a different input, starting over", path.display());
+                fresh
+            }
+        } else {
+            if resume {
+                log::warn!("No checkpoint at {}, starting over", path.display());
+            }
+            fresh
+        };
+        Ok(Self { path, state })
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.state.batch_size
+    }
+
+    pub fn committed_batches(&self) -> usize {
+        self.state.committed_batches
+    }
+
+    pub fn committed_records(&self) -> usize {
+        self.state.committed_batches * self.state.batch_size
+    }
+
+    fn commit(&mut self, committed_batches: usize) -> anyhow::Result<()> {
+        self.state.committed_batches = committed_batches;
+        // write to a temporary file first, so a crash never leaves a truncated checkpoint
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&self.state)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Remove the checkpoint after a successful run.
+    pub fn remove(self) -> anyhow::Result<()> {
+        if self.path.exists() {
+            std::fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the highest batch number up to which all batches are done.
+#[derive(Debug, Default)]
+struct Watermark {
+    next: usize,
+    pending: BTreeSet<usize>,
+}
+
+impl Watermark {
+    fn starting_at(next: usize) -> Self {
+        Self {
+            next,
+            pending: BTreeSet::new(),
+        }
+    }
+
+    /// Mark batch `seq` as done. Returns true if the watermark moved.
+    fn complete(&mut self, seq: usize) -> bool {
+        self.pending.insert(seq);
+        let before = self.next;
+        while self.pending.remove(&self.next) {
+            self.next += 1;
+        }
+        self.next != before
+    }
+}
+
+/// Collects input lines that could not be turned into records.
+pub struct RejectFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    count: usize,
+}
+
+impl RejectFile {
+    pub fn create(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let writer = BufWriter::new(File::create(&path)?);
+        Ok(Self {
+            path,
+            writer,
+            count: 0,
+        })
+    }
+
+    pub fn reject(&mut self, line: usize, error: &str, input: &str) -> anyhow::Result<()> {
+        self.count += 1;
+        let entry = serde_json::json!({ "line": line, "error": error, "input": input });
+        writeln!(self.writer, "{}", entry)?;
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        if self.count > 0 {
+            log::warn!("{} malformed lines written to {}", self.count, self.path.display());
+        } else {
+            drop(self.writer);
+            std::fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Destination of the points embedded by [`run_pipeline`].
+pub trait PointSink: Sync {
+    /// Store `points`, returning how many were stored.
+    fn store(
+        &self,
+        points: Vec<PointStruct>,
+    ) -> impl std::future::Future<Output = anyhow::Result<usize>> + Send;
+}
+
+/// Upserts points into a Qdrant collection, retrying failed requests.
+pub struct QdrantSink<'a> {
+    pub client: &'a Qdrant,
+    pub collection: &'a str,
+    pub max_retries: u32,
+}
+
+impl PointSink for QdrantSink<'_> {
+    async fn store(&self, points: Vec<PointStruct>) -> anyhow::Result<usize> {
+        upsert_with_retry(self.client, self.collection, points, self.max_retries).await
+    }
+}
+
+impl<T: PointSink> PointSink for &T {
+    async fn store(&self, points: Vec<PointStruct>) -> anyhow::Result<usize> {
+        (*self).store(points).await
+    }
+}
+
+/// Stores points in both sinks.
+impl<A: PointSink, B: PointSink> PointSink for (A, B) {
+    async fn store(&self, points: Vec<PointStruct>) -> anyhow::Result<usize> {
+        let (stored, _) = futures::try_join!(self.0.store(points.clone()), self.1.store(points))?;
+        Ok(stored)
+    }
+}
+
+/// Embed `records` on `config.threads` threads and store them in `sink`.
+///
+/// Records are read lazily in batches of `config.upsert_batch_size`. Each batch is embedded
+/// by one worker in padded sub-batches of `config.embed_batch_size` and stored with at most
+/// `config.upsert_concurrency` requests in flight. Returns the number of stored points.
+///
+/// With a `checkpoint`, already committed batches are skipped and the checkpoint is updated
+/// as batches get stored. Its batch size takes precedence over the configured one.
+///
+/// Must be called from a multi-threaded tokio runtime.
+pub async fn run_pipeline(
+    sink: &impl PointSink,
+    tokenizer: &BertTokenizer,
+    session: &Session,
+    records: impl Iterator<Item = EmbedRecord> + Send,
+    total: Option<usize>,
+    config: &PipelineConfig,
+    mut checkpoint: Option<&mut Checkpoint>,
+) -> anyhow::Result<usize> {
+    let (upsert_batch_size, first_batch) = checkpoint.as_ref().map_or(
+        (config.upsert_batch_size, 0),
+        |c| (c.batch_size(), c.committed_batches()),
+    );
+    let skip = first_batch * upsert_batch_size;
+    let total = total.map(|t| t.saturating_sub(skip));
+
+    let handle = tokio::runtime::Handle::current();
+    let (batch_tx, batch_rx) = mpsc::sync_channel::<(usize, Vec<EmbedRecord>)>(config.threads);
+    let batch_rx = Mutex::new(Some(batch_rx));
+    tokio::task::block_in_place(|| {
+        std::thread::scope(|scope| {
+            let (point_tx, point_rx) =
+                tokio::sync::mpsc::channel::<anyhow::Result<(usize, Vec<PointStruct>)>>(
+                    config.upsert_concurrency * 2,
+                );
+
+            // reader
+            scope.spawn(move || {
+                let mut records = records.skip(skip).peekable();
+                let mut seq = first_batch;
+                while records.peek().is_some() {
+                    let batch = records.by_ref().take(upsert_batch_size).collect();
+                    if batch_tx.send((seq, batch)).is_err() {
+                        // the pipeline stopped early
+                        break;
+                    }
+                    seq += 1;
+                }
+            });
+
+            // embedding workers
+            for _ in 0..config.threads {
+                let batch_rx = &batch_rx;
+                let point_tx = point_tx.clone();
+                scope.spawn(move || loop {
+                    let (seq, batch) =
+                        match batch_rx.lock().unwrap().as_ref().map(|rx| rx.recv()) {
+                            Some(Ok(batch)) => batch,
+                            _ => break,
+                        };
+                    let points = embed_batch(tokenizer, session, batch, config.embed_batch_size)
+                        .map(|points| (seq, points));
+                    let failed = points.is_err();
+                    if point_tx.blocking_send(points).is_err() || failed {
+                        break;
+                    }
+                });
+            }
+            drop(point_tx);
+
+            // upserts
+            let result = handle.block_on(async {
+                let mut progress = Progress::new("points", total, config.report_interval);
+                let mut watermark = Watermark::starting_at(first_batch);
+                let upserts = futures::stream::unfold(point_rx, |mut rx| async {
+                    rx.recv().await.map(|points| (points, rx))
+                })
+                .map(|points| async {
+                    let (seq, points) = points?;
+                    let stored = sink.store(points).await?;
+                    anyhow::Ok((seq, stored))
+                })
+                .buffer_unordered(config.upsert_concurrency);
+                let mut upserts = std::pin::pin!(upserts);
+
+                while let Some(upserted) = upserts.next().await {
+                    let (seq, stored) = upserted?;
+                    if watermark.complete(seq) {
+                        if let Some(checkpoint) = checkpoint.as_deref_mut() {
+                            checkpoint.commit(watermark.next)?;
+                        }
+                    }
+                    progress.advance(stored);
+                }
+                progress.finish();
+                Ok(progress.done)
+            });
+            // unblock the reader and the workers if we stopped on an error
+            batch_rx.lock().unwrap().take();
+            result
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array3;
+
+    /// Token embeddings of a model that attends to every unmasked token of its row: the
+    /// token id and the row's sum of unmasked ids, or zeros for padding.
+    fn fake_model(token_ids: &Array2<i64>, attentions: &Array2<i64>) -> Array3<f32> {
+        let (rows, cols) = token_ids.dim();
+        Array3::from_shape_fn((rows, cols, 2), |(row, col, dim)| {
+            let attended: i64 = (0..cols)
+                .map(|c| token_ids[[row, c]] * attentions[[row, c]])
+                .sum();
+            let value = if dim == 0 { token_ids[[row, col]] } else { attended };
+            (value * attentions[[row, col]]) as f32
+        })
+    }
+
+    fn embed(encodings: &[Vec<i64>]) -> Vec<Vec<f32>> {
+        let (token_ids, attentions) = padded_batch(encodings, 0);
+        mean_pool(fake_model(&token_ids, &attentions).view(), encodings).unwrap()
+    }
+
+    #[test]
+    fn prefix_ids_are_unique() {
+        assert_eq!(prefix_to_id("ab"), Some(PointId::from(0x6261)));
+        assert_ne!(prefix_to_num("abcdefgh"), prefix_to_num("abcdefg"));
+        assert_eq!(prefix_to_num("abcdefghi"), None);
+        assert_eq!(prefix_to_num("поиск"), None);
+    }
+
+    #[test]
+    fn padding_doesnt_change_embeddings() {
+        let encodings = vec![vec![101, 7, 8, 9, 102], vec![101, 5, 102]];
+        let batch = embed(&encodings);
+        assert_eq!(batch[0], embed(&encodings[..1])[0]);
+        assert_eq!(batch[1], embed(&encodings[1..])[0]);
+        assert_eq!(batch[1], vec![(101 + 5 + 102) as f32 / 3.0, 208.0]);
+    }
+
+    #[test]
+    #[ignore = "needs the ONNX model at MODEL_PATH"]
+    fn batch_embeddings_match_single_embeddings() {
+        let tokenizer = BertTokenizer::from_file_with_special_token_mapping(
+            "vocab.txt",
+            true,
+            false,
+            "special_tokens_map.json",
+        )
         .unwrap();
-    let pooled = output.view().mean_axis(Axis(1)).unwrap();
-    pooled.as_slice().unwrap().to_vec()
+        let environment = std::sync::Arc::new(ort::Environment::builder().build().unwrap());
+        let session = ort::SessionBuilder::new(&environment)
+            .unwrap()
+            .with_model_from_file(MODEL_PATH)
+            .unwrap();
+        let texts = ["qdrant", "how to create a collection with a payload index"];
+        let batch = get_embeddings(&tokenizer, &session, &texts).unwrap();
+        for (text, batched) in texts.iter().zip(batch) {
+            let single = get_embeddings(&tokenizer, &session, &[text]).unwrap();
+            for (a, b) in single[0].iter().zip(&batched) {
+                assert!((a - b).abs() < 1e-4, "{text}: {a} != {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn watermark_waits_for_gaps() {
+        let mut watermark = Watermark::starting_at(3);
+        assert!(!watermark.complete(4));
+        assert_eq!(watermark.next, 3);
+        assert!(watermark.complete(3));
+        assert_eq!(watermark.next, 5);
+        assert!(watermark.complete(5));
+        assert_eq!(watermark.next, 6);
+    }
+
+    #[test]
+    fn checkpoint_resume_matches_input() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.json", std::process::id()));
+        let mut checkpoint = Checkpoint::open(&path, "site", "abc", 10, false).unwrap();
+        checkpoint.commit(4).unwrap();
+
+        let resumed = Checkpoint::open(&path, "site", "abc", 99, true).unwrap();
+        assert_eq!(resumed.committed_records(), 40);
+        assert_eq!(resumed.batch_size(), 10);
+
+        let changed = Checkpoint::open(&path, "site", "def", 99, true).unwrap();
+        assert_eq!(changed.committed_batches(), 0);
+        assert_eq!(changed.batch_size(), 99);
+
+        let not_resumed = Checkpoint::open(&path, "site", "abc", 99, false).unwrap();
+        assert_eq!(not_resumed.committed_batches(), 0);
+
+        resumed.remove().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn format_duration_units() {
+        assert_eq!(format_duration(5.4), "5s");
+        assert_eq!(format_duration(125.0), "2m05s");
+        assert_eq!(format_duration(7260.0), "2h01m");
+    }
+
+    #[test]
+    fn progress_status_with_total() {
+        let mut progress = Progress::new("points", Some(100), Duration::from_secs(3600));
+        progress.advance(40);
+        assert!(progress.status().starts_with("40/100 points, "));
+        assert!(progress.status().contains("ETA"));
+    }
+
+    #[test]
+    fn progress_status_without_total() {
+        let mut progress = Progress::new("points", None, Duration::from_secs(3600));
+        progress.advance(7);
+        assert!(progress.status().starts_with("7 points, "));
+        assert!(!progress.status().contains("ETA"));
+    }
 }
@@ -0,0 +1,43 @@
+mod synonyms;
+
+use crate::synonyms::{get_synonyms_path, Synonyms};
+use anyhow::{Context, Result};
+
+/// Print the synonyms found in a query and the variants it is expanded to.
+///
+/// Usage: `expand_synonyms [--limit N] <query>...`, with the dictionary at `SYNONYMS_PATH`.
+fn main() -> Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut limit = 4;
+    if let Some(idx) = args.iter().position(|arg| arg == "--limit") {
+        limit = args
+            .get(idx + 1)
+            .and_then(|v| v.parse().ok())
+            .context("--limit needs a number")?;
+        args.drain(idx..idx + 2);
+    }
+    let query = args.join(" ");
+    if query.is_empty() {
+        anyhow::bail!("Usage: expand_synonyms [--limit N] <query>...");
+    }
+
+    let path = get_synonyms_path();
+    let content = std::fs::read_to_string(&path).with_context(|| format!("Reading {path}"))?;
+    let synonyms = Synonyms::parse(&content).with_context(|| format!("Parsing {path}"))?;
+    println!("{} synonym groups in {path}", synonyms.len());
+
+    let matches = synonyms.matches(&query);
+    if matches.is_empty() {
+        println!("No synonyms in `{query}`");
+        return Ok(());
+    }
+    for (term, others) in matches {
+        println!("{term}: {}", others.join(", "));
+    }
+    println!("Variants:");
+    println!("  {query}");
+    for variant in synonyms.expand(&query, limit) {
+        println!("  {variant}");
+    }
+    Ok(())
+}
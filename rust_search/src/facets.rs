@@ -0,0 +1,127 @@
+//! Facet counts of `/api/search`, e.g. the number of text matches per section.
+
+use std::collections::BTreeMap;
+
+use qdrant_client::qdrant::facet_value::Variant;
+use qdrant_client::qdrant::{FacetCountsBuilder, FacetHit, Filter};
+use qdrant_client::{Qdrant, QdrantError};
+use serde::Serialize;
+
+use crate::common::{env_or, COLLECTION_NAME};
+
+/// Keyword-indexed payload fields that can be counted
+pub const FACET_FIELDS: &[&str] = &["sections", "partition", "tag"];
+
+/// Values counted per field.
+pub fn facet_limit() -> u64 {
+    env_or("SEARCH_FACET_LIMIT", 20)
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: u64,
+}
+
+impl FacetCount {
+    fn from_hit(hit: FacetHit) -> Option<Self> {
+        let value = match hit.value?.variant? {
+            Variant::StringValue(value) => value,
+            Variant::IntegerValue(value) => value.to_string(),
+            Variant::BoolValue(value) => value.to_string(),
+        };
+        Some(Self {
+            value,
+            count: hit.count,
+        })
+    }
+}
+
+/// The comma-separated `facets` parameter, or the first field that can't be counted.
+pub fn parse_facets(facets: &str) -> Result<Vec<&'static str>, String> {
+    let mut fields = vec![];
+    for name in facets.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let field = FACET_FIELDS
+            .iter()
+            .find(|field| **field == name)
+            .ok_or_else(|| name.to_string())?;
+        if !fields.contains(field) {
+            fields.push(*field);
+        }
+    }
+    Ok(fields)
+}
+
+/// Count the values of `fields` among the points matching `filter`, most frequent first.
+pub async fn facet_counts(
+    client: &Qdrant,
+    fields: &[&'static str],
+    filter: Filter,
+    limit: u64,
+) -> Result<BTreeMap<&'static str, Vec<FacetCount>>, QdrantError> {
+    let responses = futures::future::try_join_all(fields.iter().map(|field| {
+        client.facet(
+            FacetCountsBuilder::new(COLLECTION_NAME, *field)
+                .filter(filter.clone())
+                .limit(limit),
+        )
+    }))
+    .await?;
+    Ok(fields
+        .iter()
+        .copied()
+        .zip(responses)
+        .map(|(field, response)| {
+            let counts = response
+                .hits
+                .into_iter()
+                .filter_map(FacetCount::from_hit)
+                .collect();
+            (field, counts)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qdrant_client::qdrant::FacetValue;
+
+    #[test]
+    fn facets_are_parsed() {
+        assert_eq!(
+            parse_facets("sections, tag,sections,"),
+            Ok(vec!["sections", "tag"])
+        );
+        assert_eq!(parse_facets(""), Ok(vec![]));
+        assert_eq!(parse_facets("partition,text"), Err("text".to_string()));
+    }
+
+    #[test]
+    fn facet_values_are_strings() {
+        let hit = |variant| FacetHit {
+            value: Some(FacetValue {
+                variant: Some(variant),
+            }),
+            count: 12,
+        };
+        assert_eq!(
+            FacetCount::from_hit(hit(Variant::StringValue("cloud".to_string()))),
+            Some(FacetCount {
+                value: "cloud".to_string(),
+                count: 12
+            })
+        );
+        assert_eq!(
+            FacetCount::from_hit(hit(Variant::IntegerValue(3))).map(|c| c.value),
+            Some("3".to_string())
+        );
+        assert_eq!(
+            FacetCount::from_hit(FacetHit {
+                value: None,
+                count: 1
+            }),
+            None
+        );
+    }
+}
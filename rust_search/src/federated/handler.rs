@@ -0,0 +1,126 @@
+use std::time::Instant;
+
+use actix_web::http::header::ContentType;
+use actix_web::web::{Data, Query};
+use actix_web::{get, HttpResponse};
+use ort::Session;
+use qdrant_client::qdrant::ScoredPoint;
+use qdrant_client::Qdrant;
+use rust_tokenizers::tokenizer::BertTokenizer;
+use serde::Deserialize;
+
+use crate::common::get_embeddings;
+use crate::snippets::RevisionCache;
+
+use super::models::{interleave, FederatedResponse};
+use super::sources::{FederatedSources, Source, SourceEmbedding};
+
+/// Search one source, failing if it takes longer than the configured timeout.
+async fn search_source(
+    client: &Qdrant,
+    federated: &FederatedSources,
+    revisions: &RevisionCache,
+    source: &Source,
+    query: &str,
+    dense: Option<&[f32]>,
+    quota: u64,
+) -> anyhow::Result<Vec<ScoredPoint>> {
+    let search = async {
+        let revision = if source.revisioned {
+            Some(revisions.get(client).await?)
+        } else {
+            None
+        };
+        let response = client
+            .query(source.build_query(query, dense, revision, quota))
+            .await?;
+        anyhow::Ok(response.result)
+    };
+    match tokio::time::timeout(federated.timeout, search).await {
+        Ok(result) => result,
+        Err(_) => anyhow::bail!("timed out after {:?}", federated.timeout),
+    }
+}
+
+#[derive(Deserialize)]
+struct FederatedSearch {
+    q: String,
+    /// Comma-separated names of the sources to search, all registered ones by default
+    sources: Option<String>,
+    quota: Option<u64>,
+    limit: Option<usize>,
+}
+
+#[get("/api/federated")]
+pub async fn federated_handler(
+    query: Query<FederatedSearch>,
+    context: Data<(BertTokenizer, Session, Qdrant)>,
+    federated: Data<FederatedSources>,
+    revisions: Data<RevisionCache>,
+) -> HttpResponse {
+    let time_start = Instant::now();
+    let (tokenizer, session, qdrant) = context.get_ref();
+    let sources = federated.requested(query.sources.as_deref());
+    let quota = query.quota.unwrap_or(federated.quota);
+    let limit = query.limit.unwrap_or(federated.limit);
+
+    // one embedding for all sources of the service's model
+    let mut warnings = vec![];
+    let dense = if sources
+        .iter()
+        .any(|s| s.embedding == SourceEmbedding::Local)
+    {
+        match get_embeddings(tokenizer, session, &[&query.q]) {
+            Ok(mut embeddings) => embeddings.pop(),
+            Err(e) => {
+                log::warn!("Local embedding failed, using server-side inference: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let searches = sources.iter().map(|source| {
+        search_source(
+            qdrant,
+            federated.get_ref(),
+            revisions.get_ref(),
+            source,
+            &query.q,
+            dense.as_deref(),
+            quota,
+        )
+    });
+    let responses = futures::future::join_all(searches).await;
+
+    let mut results = vec![];
+    for (source, response) in sources.iter().copied().zip(responses) {
+        match response {
+            Ok(points) => results.push((source, points)),
+            Err(e) => {
+                log::warn!("Federated source {} failed: {}", source.name, e);
+                warnings.push(format!("{}: {}", source.name, e));
+            }
+        }
+    }
+    if results.is_empty() && !sources.is_empty() {
+        return HttpResponse::InternalServerError().body(warnings.join("\n"));
+    }
+
+    let hits = interleave(results, limit);
+    log::info!(
+        "federated query={} hits={} failed={}",
+        query.q,
+        hits.len(),
+        warnings.len()
+    );
+    HttpResponse::Ok().insert_header(ContentType::json()).body(
+        serde_json::to_string(&FederatedResponse {
+            result: hits,
+            warnings,
+            time: time_start.elapsed().as_micros() as f64 / 1_000_000.0,
+        })
+        .expect("Failed to serialize response"),
+    )
+}
@@ -0,0 +1,6 @@
+mod handler;
+mod models;
+mod sources;
+
+pub use handler::federated_handler;
+pub use sources::FederatedSources;
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use qdrant_client::qdrant::{ScoredPoint, Value};
+use serde::Serialize;
+
+use super::sources::{payload_str, Source};
+
+/// A result of one source, with its score normalized within the source.
+#[derive(Debug, Serialize)]
+pub struct FederatedHit {
+    pub source: &'static str,
+    pub score: f32,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub payload: HashMap<String, Value>,
+}
+
+#[derive(Serialize)]
+pub struct FederatedResponse {
+    pub result: Vec<FederatedHit>,
+    /// Sources that failed or timed out, whose results are missing
+    pub warnings: Vec<String>,
+    pub time: f64,
+}
+
+/// Min-max normalize the scores of one source to `[0, 1]`, best first.
+///
+/// Scores of different collections aren't comparable, e.g. cosine similarities of different
+/// models or fused ranks. A single hit, or hits with equal scores, get a score of 1.
+pub fn normalize_scores(scores: &[f32]) -> Vec<f32> {
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let min = scores.iter().copied().fold(f32::INFINITY, f32::min);
+    let range = max - min;
+    scores
+        .iter()
+        .map(|score| {
+            if range > f32::EPSILON {
+                (score - min) / range
+            } else {
+                1.0
+            }
+        })
+        .collect()
+}
+
+/// Label and normalize the points of every source and interleave them by normalized score.
+///
+/// `results` are in the order of precedence of the sources, which breaks ties together with
+/// the rank within the source.
+pub fn interleave(results: Vec<(&Source, Vec<ScoredPoint>)>, limit: usize) -> Vec<FederatedHit> {
+    let mut hits = vec![];
+    for (precedence, (source, points)) in results.into_iter().enumerate() {
+        let scores: Vec<f32> = points.iter().map(|p| p.score).collect();
+        for (rank, (point, score)) in points
+            .into_iter()
+            .zip(normalize_scores(&scores))
+            .enumerate()
+        {
+            let hit = FederatedHit {
+                source: source.name,
+                score,
+                title: payload_str(&point.payload, source.title_field),
+                url: payload_str(&point.payload, source.url_field),
+                payload: point.payload,
+            };
+            hits.push((rank, precedence, hit));
+        }
+    }
+    hits.sort_by(|a, b| {
+        b.2.score
+            .total_cmp(&a.2.score)
+            .then(a.0.cmp(&b.0))
+            .then(a.1.cmp(&b.1))
+    });
+    hits.into_iter()
+        .take(limit)
+        .map(|(_, _, hit)| hit)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(score: f32, url: &str) -> ScoredPoint {
+        ScoredPoint {
+            score,
+            payload: HashMap::from([("url".to_string(), Value::from(url))]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scores_are_normalized_per_source() {
+        assert_eq!(normalize_scores(&[4.0, 3.0, 2.0]), vec![1.0, 0.5, 0.0]);
+        assert_eq!(normalize_scores(&[12.0]), vec![1.0]);
+        assert!(normalize_scores(&[]).is_empty());
+    }
+
+    #[test]
+    fn sources_are_interleaved() {
+        let known = Source::known();
+        let results = vec![
+            (
+                &known[0],
+                vec![point(0.9, "/a"), point(0.8, "/b"), point(0.1, "/c")],
+            ),
+            (&known[1], vec![point(20.0, "/d"), point(10.0, "/e")]),
+        ];
+        let hits = interleave(results, 4);
+        let order: Vec<(&str, Option<&str>)> =
+            hits.iter().map(|h| (h.source, h.url.as_deref())).collect();
+        assert_eq!(
+            order,
+            vec![
+                ("site", Some("/a")),
+                ("sections", Some("/d")),
+                ("site", Some("/b")),
+                ("sections", Some("/e")),
+            ]
+        );
+    }
+}
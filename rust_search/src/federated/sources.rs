@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use qdrant_client::qdrant::{
+    Condition, Document, Filter, QueryPoints, QueryPointsBuilder, Value, VectorInput,
+};
+
+use crate::common::{
+    env_or, COLLECTION_NAME, SECTION_COLLECTION_NAME, SKILLS_COLLECTION_NAME,
+    SNIPPET_COLLECTION_NAME,
+};
+use crate::sections::NEURAL_ENCODER;
+use crate::snippet::{DENSE_VECTOR, SNIPPET_ENCODER};
+use crate::snippets::snippets_local_embedding;
+
+/// How the query is embedded for a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceEmbedding {
+    /// The service's ONNX model, computed once per request and shared by all such sources
+    Local,
+    /// Inference by Qdrant with the given model
+    Server(&'static str),
+}
+
+/// A collection searched by `/api/federated`.
+#[derive(Debug, Clone)]
+pub struct Source {
+    /// Label of the results of this source
+    pub name: &'static str,
+    pub collection: &'static str,
+    pub embedding: SourceEmbedding,
+    /// Named vector to search, the default vector if `None`
+    pub using: Option<&'static str>,
+    /// Only search the latest revision, see `snippet`
+    pub revisioned: bool,
+    /// Payload fields of the result title and URL, nested fields separated by `.`
+    pub title_field: &'static str,
+    pub url_field: &'static str,
+}
+
+impl Source {
+    /// All sources that can be registered, in the order of precedence on equal scores.
+    pub(super) fn known() -> Vec<Self> {
+        vec![
+            Self {
+                name: "site",
+                collection: COLLECTION_NAME,
+                embedding: SourceEmbedding::Local,
+                using: None,
+                revisioned: false,
+                title_field: "text",
+                url_field: "url",
+            },
+            // embedded with `NEURAL_ENCODER`, which is the service's model
+            Self {
+                name: "sections",
+                collection: SECTION_COLLECTION_NAME,
+                embedding: SourceEmbedding::Local,
+                using: None,
+                revisioned: false,
+                title_field: "title",
+                url_field: "url",
+            },
+            Self {
+                name: "skills",
+                collection: SKILLS_COLLECTION_NAME,
+                embedding: SourceEmbedding::Server(SNIPPET_ENCODER),
+                using: None,
+                revisioned: false,
+                title_field: "name",
+                url_field: "url",
+            },
+            Self {
+                name: "snippets",
+                collection: SNIPPET_COLLECTION_NAME,
+                embedding: if snippets_local_embedding() {
+                    SourceEmbedding::Local
+                } else {
+                    SourceEmbedding::Server(SNIPPET_ENCODER)
+                },
+                using: Some(DENSE_VECTOR),
+                revisioned: true,
+                title_field: "package_name",
+                url_field: "source.url",
+            },
+        ]
+    }
+
+    /// Nearest neighbours of the query, `dense` if embedded locally.
+    pub fn build_query(
+        &self,
+        query: &str,
+        dense: Option<&[f32]>,
+        revision: Option<i64>,
+        limit: u64,
+    ) -> QueryPoints {
+        let input = match (self.embedding, dense) {
+            (SourceEmbedding::Local, Some(dense)) => VectorInput::new_dense(dense.to_vec()),
+            // the service's model is also the encoder of the sections
+            (SourceEmbedding::Local, None) => {
+                VectorInput::from(Document::new(query, NEURAL_ENCODER))
+            }
+            (SourceEmbedding::Server(model), _) => VectorInput::from(Document::new(query, model)),
+        };
+        let mut builder = QueryPointsBuilder::new(self.collection)
+            .query(input)
+            .limit(limit)
+            .with_payload(true);
+        if let Some(using) = self.using {
+            builder = builder.using(using);
+        }
+        if let Some(revision) = revision.filter(|_| self.revisioned) {
+            builder = builder.filter(Filter::must([Condition::matches("revision", revision)]));
+        }
+        builder.build()
+    }
+}
+
+/// String at the dotted `path` of a payload.
+pub fn payload_str(payload: &HashMap<String, Value>, path: &str) -> Option<String> {
+    let mut parts = path.split('.');
+    let mut value = payload.get(parts.next()?)?;
+    for part in parts {
+        value = value.get_value(part)?;
+    }
+    value.as_str().cloned()
+}
+
+/// The registered sources and how their results are combined.
+#[derive(Debug, Clone)]
+pub struct FederatedSources {
+    /// Sources named in `FEDERATED_SOURCES` (default: all)
+    pub sources: Vec<Source>,
+    /// Results per source (`FEDERATED_QUOTA`)
+    pub quota: u64,
+    /// Results in total (`FEDERATED_LIMIT`)
+    pub limit: usize,
+    /// How long a source may take before it is skipped (`FEDERATED_TIMEOUT_MS`)
+    pub timeout: Duration,
+}
+
+impl FederatedSources {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let names = env_or(
+            "FEDERATED_SOURCES",
+            "site,sections,skills,snippets".to_string(),
+        );
+        Ok(Self {
+            sources: Self::select(&names)?,
+            quota: env_or("FEDERATED_QUOTA", 3_u64).max(1),
+            limit: env_or("FEDERATED_LIMIT", 10_usize).max(1),
+            timeout: Duration::from_millis(env_or("FEDERATED_TIMEOUT_MS", 2000)),
+        })
+    }
+
+    /// Known sources named in the comma-separated `names`, in their order of precedence.
+    fn select(names: &str) -> anyhow::Result<Vec<Source>> {
+        let names: Vec<&str> = names
+            .split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .collect();
+        let known = Source::known();
+        if let Some(unknown) = names.iter().find(|n| !known.iter().any(|s| &s.name == *n)) {
+            anyhow::bail!("Unknown federated source `{unknown}`");
+        }
+        Ok(known
+            .into_iter()
+            .filter(|s| names.contains(&s.name))
+            .collect())
+    }
+
+    /// Registered sources named in `names`, or all of them.
+    pub fn requested(&self, names: Option<&str>) -> Vec<&Source> {
+        let Some(names) = names else {
+            return self.sources.iter().collect();
+        };
+        let names: Vec<&str> = names.split(',').map(str::trim).collect();
+        self.sources
+            .iter()
+            .filter(|s| names.contains(&s.name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_are_selected_by_name() {
+        let names: Vec<&str> = FederatedSources::select("snippets, site")
+            .unwrap()
+            .iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, vec!["site", "snippets"]);
+        assert!(FederatedSources::select("site,docs").is_err());
+    }
+
+    #[test]
+    fn only_revisioned_sources_are_filtered() {
+        let known = Source::known();
+        let site = known[0].build_query("hnsw", Some(&[0.1, 0.2]), Some(3), 5);
+        assert!(site.filter.is_none());
+        assert!(site.using.is_none());
+        let snippets = known[3].build_query("hnsw", Some(&[0.1, 0.2]), Some(3), 5);
+        assert_eq!(snippets.filter.unwrap().must.len(), 1);
+        assert_eq!(snippets.using.as_deref(), Some(DENSE_VECTOR));
+        assert_eq!(snippets.limit, Some(5));
+    }
+}
@@ -0,0 +1,95 @@
+//! Response format negotiation.
+
+/// Formats a handler can render its result in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Json,
+    Markdown,
+    Html,
+}
+
+impl ResponseFormat {
+    fn from_param(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "markdown" | "md" => Some(Self::Markdown),
+            "html" => Some(Self::Html),
+            _ => None,
+        }
+    }
+
+    fn from_media_type(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "application/json" => Some(Self::Json),
+            "text/markdown" => Some(Self::Markdown),
+            "text/html" => Some(Self::Html),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Html => "text/html; charset=utf-8",
+        }
+    }
+}
+
+/// Resolve the response format: the `format` query parameter overrides the `Accept` header.
+///
+/// Media types in `Accept` are tried in the order given, quality values are not weighted.
+/// Formats not in `supported` are skipped. Default is markdown.
+pub fn resolve_format(
+    format_param: Option<&str>,
+    accept: Option<&str>,
+    supported: &[ResponseFormat],
+) -> ResponseFormat {
+    let param = format_param.and_then(ResponseFormat::from_param);
+    if let Some(format) = param.filter(|f| supported.contains(f)) {
+        return format;
+    }
+    accept
+        .into_iter()
+        .flat_map(|accept| accept.split(','))
+        .filter_map(|part| ResponseFormat::from_media_type(part.split(';').next()?))
+        .find(|f| supported.contains(f))
+        .unwrap_or(ResponseFormat::Markdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: &[ResponseFormat] = &[
+        ResponseFormat::Json,
+        ResponseFormat::Markdown,
+        ResponseFormat::Html,
+    ];
+
+    #[test]
+    fn param_overrides_accept() {
+        assert_eq!(
+            resolve_format(Some("JSON"), Some("text/html"), ALL),
+            ResponseFormat::Json
+        );
+    }
+
+    #[test]
+    fn accept_header_in_order() {
+        assert_eq!(
+            resolve_format(None, Some("text/plain, text/html;q=0.9, application/json"), ALL),
+            ResponseFormat::Html
+        );
+    }
+
+    #[test]
+    fn unsupported_falls_back_to_markdown() {
+        let supported = &[ResponseFormat::Json, ResponseFormat::Markdown];
+        assert_eq!(
+            resolve_format(Some("html"), Some("text/html"), supported),
+            ResponseFormat::Markdown
+        );
+        assert_eq!(resolve_format(Some("xml"), None, ALL), ResponseFormat::Markdown);
+    }
+}
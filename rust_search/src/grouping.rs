@@ -0,0 +1,155 @@
+//! Grouped results of `/api/search`: one hit per page, with other hits of the page as support.
+
+use std::collections::HashSet;
+
+use qdrant_client::qdrant::group_id::Kind;
+use qdrant_client::qdrant::{GroupId, PointGroup, QueryPointGroups, QueryPoints, ScoredPoint};
+
+/// Payload field the results are grouped by
+pub const GROUP_BY_URL: &str = "url";
+/// Supporting hits per page, unless requested otherwise
+pub const DEFAULT_SUPPORTING: u64 = 2;
+pub const MAX_SUPPORTING: u64 = 10;
+
+/// A search result, with the other hits of its page if grouped.
+pub struct Hit {
+    pub point: ScoredPoint,
+    pub supporting: Option<Vec<ScoredPoint>>,
+}
+
+impl From<ScoredPoint> for Hit {
+    fn from(point: ScoredPoint) -> Self {
+        Self {
+            point,
+            supporting: None,
+        }
+    }
+}
+
+/// The grouped variant of `query`: `limit` pages with up to `supporting` extra hits each.
+pub fn group_query(query: QueryPoints, group_by: &str, supporting: u64) -> QueryPointGroups {
+    QueryPointGroups {
+        collection_name: query.collection_name,
+        prefetch: query.prefetch,
+        query: query.query,
+        using: query.using,
+        filter: query.filter,
+        params: query.params,
+        score_threshold: query.score_threshold,
+        with_payload: query.with_payload,
+        with_vectors: query.with_vectors,
+        lookup_from: query.lookup_from,
+        limit: query.limit,
+        group_size: Some(supporting + 1),
+        group_by: group_by.to_string(),
+        read_consistency: query.read_consistency,
+        with_lookup: None,
+        timeout: query.timeout,
+        shard_key_selector: query.shard_key_selector,
+    }
+}
+
+fn group_id_to_hash(id: Option<GroupId>) -> String {
+    match id.and_then(|id| id.kind) {
+        None => "".to_string(),
+        Some(Kind::UnsignedValue(num)) => format!("{}", num),
+        Some(Kind::IntegerValue(num)) => format!("{}", num),
+        Some(Kind::StringValue(value)) => value,
+    }
+}
+
+/// Merge the groups of the filtering tiers like `merge_results`, but keep one group per page.
+///
+/// A page found by a higher tier keeps the hits of that tier.
+pub fn merge_groups(results: Vec<Vec<PointGroup>>, limit: usize) -> Vec<Hit> {
+    let mut seen = HashSet::new();
+    let mut res = vec![];
+    for groups in results {
+        for group in groups {
+            let mut hits = group.hits.into_iter();
+            let Some(point) = hits.next() else {
+                continue;
+            };
+            if !seen.insert(group_id_to_hash(group.id)) {
+                continue;
+            }
+            res.push(Hit {
+                point,
+                supporting: Some(hits.collect()),
+            });
+        }
+        if res.len() >= limit {
+            break;
+        }
+    }
+    res.truncate(limit);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qdrant_client::qdrant::{PointId, QueryPointsBuilder};
+
+    fn group(url: &str, ids: &[u64]) -> PointGroup {
+        PointGroup {
+            id: Some(GroupId {
+                kind: Some(Kind::StringValue(url.to_string())),
+            }),
+            hits: ids
+                .iter()
+                .map(|&id| ScoredPoint {
+                    id: Some(PointId::from(id)),
+                    ..Default::default()
+                })
+                .collect(),
+            lookup: None,
+        }
+    }
+
+    fn ids(hit: &Hit) -> (u64, Vec<u64>) {
+        let id = |point: &ScoredPoint| match point.id.clone().and_then(|id| id.point_id_options) {
+            Some(qdrant_client::qdrant::point_id::PointIdOptions::Num(num)) => num,
+            _ => panic!("unexpected point id"),
+        };
+        let supporting = hit.supporting.as_ref().unwrap();
+        (id(&hit.point), supporting.iter().map(id).collect())
+    }
+
+    #[test]
+    fn pages_keep_their_highest_tier() {
+        let results = vec![
+            vec![group("/a", &[1, 2])],
+            vec![group("/b", &[3]), group("/a", &[4, 5, 6])],
+            vec![group("/c", &[7, 8])],
+        ];
+        let hits = merge_groups(results, 3);
+        let hits: Vec<_> = hits.iter().map(ids).collect();
+        assert_eq!(hits, vec![(1, vec![2]), (3, vec![]), (7, vec![8])]);
+    }
+
+    #[test]
+    fn merging_stops_at_the_limit() {
+        let results = vec![
+            vec![group("/a", &[1]), group("/b", &[2]), group("/c", &[3])],
+            vec![group("/d", &[4])],
+        ];
+        assert_eq!(merge_groups(results, 2).len(), 2);
+    }
+
+    #[test]
+    fn grouped_query_keeps_the_request() {
+        let query = QueryPointsBuilder::new("site")
+            .query(vec![0.1, 0.2])
+            .limit(5)
+            .with_payload(true)
+            .build();
+        let grouped = group_query(query, GROUP_BY_URL, 2);
+        assert_eq!(grouped.collection_name, "site");
+        assert_eq!(grouped.group_by, "url");
+        assert_eq!(grouped.limit, Some(5));
+        assert_eq!(grouped.group_size, Some(3));
+        assert!(grouped.query.is_some());
+        assert!(grouped.with_payload.is_some());
+    }
+}
@@ -1,101 +1,96 @@
 mod common;
+mod prefix_store;
+mod prefix_vocab;
 
-use crate::common::{get_embedding, get_qdrant_url, PREFIX_COLLECTION_NAME};
+use crate::common::{
+    get_qdrant_url, hash_bytes, prefix_to_id, prefix_to_num, run_pipeline, Checkpoint, EmbedRecord,
+    PipelineConfig, PointSink, QdrantSink, PREFIX_COLLECTION_NAME, SECTION_COLLECTION_NAME,
+    SITE_DATA,
+};
+use crate::prefix_store::{get_prefix_store_path, write_prefix_store};
+use crate::prefix_vocab::{build_prefixes, count_words, VocabConfig, VocabSource};
 use anyhow::Result;
 use itertools::Itertools;
 use ort::{Environment, SessionBuilder};
+use qdrant_client::qdrant::point_id::PointIdOptions;
+use qdrant_client::qdrant::vectors::VectorsOptions;
+use qdrant_client::qdrant::{vector, Vector};
 use qdrant_client::qdrant::{
-    vectors_config::Config, OptimizersConfigDiff, PointId, PointStruct, VectorParams, Vectors,
+    vectors_config::Config, OptimizersConfigDiff, PointId, PointStruct, VectorParams,
     VectorsConfig,
 };
-use qdrant_client::qdrant::{CreateCollection, Distance, UpsertPointsBuilder, Value};
+use qdrant_client::qdrant::{
+    CreateCollection, DeletePointsBuilder, Distance, PayloadIncludeSelector, ScrollPointsBuilder,
+    Value,
+};
 use qdrant_client::Qdrant;
 use rust_tokenizers::tokenizer::BertTokenizer;
-use std::collections::{HashMap, HashSet};
-use std::{io::Write, sync::Arc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 use tokio::main;
 
 const MODEL_PATH: &str = "all-MiniLM-L6-v2.onnx";
 const VOCAB_PATH: &str = "vocab.txt";
 const SPECIAL_TOKEN_PATH: &str = "special_tokens_map.json";
+const CHECKPOINT_PATH: &str = "index_prefix.checkpoint.json";
+const WORDS_PATH: &str = "words.txt";
+const SCROLL_LIMIT: u32 = 1000;
 
-fn prefix_to_id(prefix: &str) -> PointId {
-    let len = prefix.len();
-    PointId::from(u64::from_le_bytes(if len < 8 {
-        let mut result = [0_u8; 8];
-        result[..len].copy_from_slice(prefix.as_bytes());
-        result
-    } else {
-        prefix.as_bytes()[..8].try_into().unwrap()
-    }))
+/// Where the embedded prefixes are stored (`PREFIX_OUTPUT`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PrefixOutput {
+    /// The `prefix-cache` collection
+    Qdrant,
+    /// A local prefix store file, see `prefix_store`
+    File,
+    /// Both of the above
+    Both,
 }
 
-fn n_chars(word: &str, n: usize) -> &str {
-    if word.len() <= n {
-        word
-    } else {
-        &word[..word.char_indices().nth(n).map_or(0, |(i, _)| i)]
+impl PrefixOutput {
+    fn from_env() -> Result<Self> {
+        match std::env::var("PREFIX_OUTPUT").as_deref() {
+            Err(_) | Ok("qdrant") => Ok(Self::Qdrant),
+            Ok("file") => Ok(Self::File),
+            Ok("both") => Ok(Self::Both),
+            Ok(other) => anyhow::bail!("Unknown prefix output `{other}`"),
+        }
     }
 }
 
-#[main]
-async fn main() -> Result<()> {
-    // Get word prefixes
-    let words = std::fs::read_to_string("words.txt")?;
-    let mut prefixes = HashSet::new();
-    for word in words.lines() {
-        for n in 1..6 {
-            prefixes.insert(n_chars(word, n));
-        }
-    }
-    prefixes.remove("");
-    println!("{} prefixes found", prefixes.len());
+/// Collects the embedded prefixes for the prefix store file.
+#[derive(Default)]
+struct StoreSink {
+    vectors: Mutex<BTreeMap<String, Vec<f32>>>,
+}
 
-    // embed all word prefixes
-    let tokenizer = BertTokenizer::from_file_with_special_token_mapping(
-        VOCAB_PATH,
-        true,
-        false,
-        SPECIAL_TOKEN_PATH,
-    )
-    .unwrap();
-    let env = Arc::new(Environment::builder().build()?);
-    let session = SessionBuilder::new(&env)?.with_model_from_file(MODEL_PATH)?;
-    let id = &mut 1_u64;
-    let stdout = std::io::stdout();
-    let mut stdout = stdout.lock();
-    let points = prefixes.into_iter().map(|prefix| {
-        let vector = get_embedding(&tokenizer, &session, prefix);
-        if (*id).is_multiple_of(100) {
-            write!(stdout, "{id}").unwrap();
-        } else {
-            write!(stdout, ".").unwrap();
-        }
-        stdout.flush().unwrap();
-        let payload = vec![("prefix".to_string(), prefix.into())]
-            .into_iter()
-            .collect::<HashMap<_, Value>>();
-
-        PointStruct {
-            id: Some(prefix_to_id(prefix)),
-            vectors: Some(Vectors::from(vector)),
-            payload,
+impl PointSink for StoreSink {
+    async fn store(&self, points: Vec<PointStruct>) -> Result<usize> {
+        let count = points.len();
+        let mut vectors = self.vectors.lock().unwrap();
+        for point in points {
+            let prefix = point
+                .payload
+                .get("prefix")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("Point without prefix"))?
+                .clone();
+            let vector = match point.vectors.and_then(|v| v.vectors_options) {
+                Some(VectorsOptions::Vector(Vector {
+                    vector: Some(vector::Vector::Dense(dense)),
+                    ..
+                })) => dense.data,
+                _ => anyhow::bail!("Point of `{prefix}` without a dense vector"),
+            };
+            vectors.insert(prefix, vector);
         }
-    });
-
-    // store the word prefixes with embedding
-    let qdrant_url = get_qdrant_url();
-    let mut builder = Qdrant::from_url(&qdrant_url);
-    if let Ok(key) = std::env::var("QDRANT_API_KEY") {
-        builder = builder.api_key(key);
+        Ok(count)
     }
-    let qdrant_client = builder.build()?;
+}
 
-    if !qdrant_client
-        .collection_exists(PREFIX_COLLECTION_NAME)
-        .await?
-    {
-        qdrant_client
+async fn ensure_prefix_collection(client: &Qdrant) -> Result<()> {
+    if !client.collection_exists(PREFIX_COLLECTION_NAME).await? {
+        client
             .create_collection(CreateCollection {
                 collection_name: PREFIX_COLLECTION_NAME.into(),
                 vectors_config: Some(VectorsConfig {
@@ -114,12 +109,237 @@ async fn main() -> Result<()> {
             })
             .await?;
     }
-    for p in &points.chunks(1024) {
-        let p: Vec<_> = p.collect();
+    Ok(())
+}
+
+/// Count the words of all configured vocabulary sources.
+async fn collect_words(
+    client: &Qdrant,
+    config: &VocabConfig,
+) -> Result<HashMap<String, usize>> {
+    let mut counts = HashMap::new();
+    for source in &config.sources {
+        match source {
+            VocabSource::Words => {
+                for word in std::fs::read_to_string(WORDS_PATH)?.lines() {
+                    let word = word.trim().to_lowercase();
+                    if !word.is_empty() {
+                        *counts.entry(word).or_default() += 1;
+                    }
+                }
+            }
+            VocabSource::Corpus => {
+                for line in std::fs::read_to_string(SITE_DATA)?.lines() {
+                    let payload: HashMap<String, serde_json::Value> =
+                        match serde_json::from_str(line) {
+                            Ok(payload) => payload,
+                            Err(_) => continue,
+                        };
+                    if let Some(text) = payload.get("text").and_then(|v| v.as_str()) {
+                        count_words(text, &mut counts);
+                    }
+                }
+            }
+            VocabSource::Sections => {
+                let mut offset = None;
+                loop {
+                    let mut request = ScrollPointsBuilder::new(SECTION_COLLECTION_NAME)
+                        .limit(SCROLL_LIMIT)
+                        .with_payload(PayloadIncludeSelector {
+                            fields: vec!["title".to_string(), "content".to_string()],
+                        });
+                    if let Some(offset) = offset.take() {
+                        request = request.offset::<PointId>(offset);
+                    }
+                    let response = client.scroll(request).await?;
+                    for point in response.result {
+                        for field in ["title", "content"] {
+                            if let Some(text) = point.payload.get(field).and_then(Value::as_str) {
+                                count_words(text, &mut counts);
+                            }
+                        }
+                    }
+                    match response.next_page_offset {
+                        Some(next) => offset = Some(next),
+                        None => break,
+                    }
+                }
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Delete all points of the prefix cache whose prefix is no longer in the vocabulary.
+async fn prune_prefixes(client: &Qdrant, keep: &HashSet<u64>) -> Result<usize> {
+    let mut stale = vec![];
+    let mut offset = None;
+    loop {
+        let mut request = ScrollPointsBuilder::new(PREFIX_COLLECTION_NAME)
+            .limit(SCROLL_LIMIT)
+            .with_payload(false);
+        if let Some(offset) = offset.take() {
+            request = request.offset::<PointId>(offset);
+        }
+        let response = client.scroll(request).await?;
+        stale.extend(
+            response
+                .result
+                .into_iter()
+                .filter_map(|point| point.id)
+                .filter(|id| match id.point_id_options {
+                    Some(PointIdOptions::Num(num)) => !keep.contains(&num),
+                    _ => true,
+                }),
+        );
+        match response.next_page_offset {
+            Some(next) => offset = Some(next),
+            None => break,
+        }
+    }
 
-        let request = UpsertPointsBuilder::new(PREFIX_COLLECTION_NAME, p);
+    let pruned = stale.len();
+    for ids in stale.chunks(SCROLL_LIMIT as usize) {
+        client
+            .delete_points(
+                DeletePointsBuilder::new(PREFIX_COLLECTION_NAME)
+                    .points(ids.to_vec())
+                    .wait(true),
+            )
+            .await?;
+    }
+    Ok(pruned)
+}
+
+#[main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let config = PipelineConfig::from_env();
+    let vocab_config = VocabConfig::from_env()?;
+    let resume = std::env::args().any(|arg| arg == "--resume");
+
+    let qdrant_url = get_qdrant_url();
+    let mut builder = Qdrant::from_url(&qdrant_url);
+    if let Ok(key) = std::env::var("QDRANT_API_KEY") {
+        builder = builder.api_key(key);
+    }
+    let qdrant_client = builder.build()?;
+
+    // Get word prefixes, sorted so that a resumed run sees them in the same order
+    let word_counts = collect_words(&qdrant_client, &vocab_config).await?;
+    let prefixes = build_prefixes(&word_counts, &vocab_config);
+    log::info!(
+        "{} prefixes found in {} distinct words",
+        prefixes.len(),
+        word_counts.len()
+    );
+
+    // embed all word prefixes
+    let tokenizer = BertTokenizer::from_file_with_special_token_mapping(
+        VOCAB_PATH,
+        true,
+        false,
+        SPECIAL_TOKEN_PATH,
+    )
+    .unwrap();
+    let env = Arc::new(Environment::builder().build()?);
+    let session = SessionBuilder::new(&env)?.with_model_from_file(MODEL_PATH)?;
+    let total = prefixes.len();
+    let mut checkpoint = Checkpoint::open(
+        CHECKPOINT_PATH,
+        PREFIX_COLLECTION_NAME,
+        &hash_bytes(
+            prefixes
+                .iter()
+                .map(|(prefix, freq)| format!("{prefix}\t{freq}"))
+                .join("\n")
+                .as_bytes(),
+        ),
+        config.upsert_batch_size,
+        resume,
+    )?;
+    let keep: HashSet<u64> = prefixes
+        .keys()
+        .filter_map(|prefix| prefix_to_num(prefix))
+        .collect();
+    let records = prefixes.iter().filter_map(|(prefix, freq)| {
+        let payload = vec![
+            ("prefix".to_string(), prefix.as_str().into()),
+            ("frequency".to_string(), (*freq as i64).into()),
+        ]
+        .into_iter()
+        .collect::<HashMap<_, Value>>();
+
+        Some(EmbedRecord {
+            id: prefix_to_id(prefix)?,
+            text: prefix.clone(),
+            payload,
+        })
+    });
+
+    // store the word prefixes with embedding
+    let output = PrefixOutput::from_env()?;
+    if output != PrefixOutput::Qdrant && resume {
+        log::warn!("--resume is ignored when writing a prefix store file");
+    }
+    let qdrant_sink = QdrantSink {
+        client: &qdrant_client,
+        collection: PREFIX_COLLECTION_NAME,
+        max_retries: config.max_retries,
+    };
+    let store_sink = StoreSink::default();
+    match output {
+        PrefixOutput::Qdrant => {
+            ensure_prefix_collection(&qdrant_client).await?;
+            run_pipeline(
+                &qdrant_sink,
+                &tokenizer,
+                &session,
+                records,
+                Some(total),
+                &config,
+                Some(&mut checkpoint),
+            )
+            .await?;
+        }
+        PrefixOutput::File => {
+            run_pipeline(
+                &store_sink,
+                &tokenizer,
+                &session,
+                records,
+                Some(total),
+                &config,
+                None,
+            )
+            .await?;
+        }
+        PrefixOutput::Both => {
+            ensure_prefix_collection(&qdrant_client).await?;
+            run_pipeline(
+                &(qdrant_sink, &store_sink),
+                &tokenizer,
+                &session,
+                records,
+                Some(total),
+                &config,
+                None,
+            )
+            .await?;
+        }
+    }
+    checkpoint.remove()?;
+
+    if output != PrefixOutput::Qdrant {
+        let path = get_prefix_store_path();
+        let vectors = store_sink.vectors.into_inner().unwrap();
+        write_prefix_store(&path, &vectors)?;
+        log::info!("{} prefix vectors written to {path}", vectors.len());
+    }
 
-        qdrant_client.upsert_points(request).await?;
+    if output != PrefixOutput::File {
+        let pruned = prune_prefixes(&qdrant_client, &keep).await?;
+        log::info!("{pruned} stale prefixes pruned");
     }
 
     Ok(())
@@ -0,0 +1,360 @@
+mod common;
+mod snippet;
+mod snippet_description;
+mod snippet_extract;
+
+use crate::common::{
+    env_or, get_qdrant_url, run_pipeline, EmbedRecord, PipelineConfig, PointSink, QdrantSink,
+    SNIPPET_COLLECTION_NAME,
+};
+use crate::snippet::{
+    bm25_document, dense_vector_size, latest_revision, Snippet, CODE_HASH_FIELD, DENSE_VECTOR,
+    LOCAL_DENSE_SIZE, PENDING_FIELD, SNIPPET_ENCODER, SPARSE_VECTOR,
+};
+use crate::snippet_description::{
+    describe_all, CachedDescriptions, ChatCompletions, DescriptionMode, NoDescriptions,
+};
+use crate::snippet_extract::{extract_snippets, markdown_files, page_url, SnippetOrigin};
+use anyhow::{Context, Result};
+use ort::{Environment, SessionBuilder};
+use qdrant_client::qdrant::vectors::VectorsOptions;
+use qdrant_client::qdrant::{
+    vector, Condition, CreateCollectionBuilder, CreateFieldIndexCollectionBuilder,
+    DeletePayloadPointsBuilder, DeletePointsBuilder, Distance, FieldType, Filter, Modifier,
+    NamedVectors, PointId, PointStruct, Range, SparseVectorParamsBuilder,
+    SparseVectorsConfigBuilder, TextIndexParamsBuilder, TokenizerType, Value, Vector,
+    VectorParamsBuilder, Vectors, VectorsConfigBuilder,
+};
+use qdrant_client::Qdrant;
+use rust_tokenizers::tokenizer::BertTokenizer;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::main;
+
+const MODEL_PATH: &str = "all-MiniLM-L6-v2.onnx";
+const VOCAB_PATH: &str = "vocab.txt";
+const SPECIAL_TOKEN_PATH: &str = "special_tokens_map.json";
+
+const TEXT_FIELDS: &[&str] = &["code", "context.before", "context.after", "description"];
+const KEYWORD_FIELDS: &[&str] = &[
+    "language",
+    "version",
+    "package_name",
+    "source.url",
+    "source.hash",
+];
+
+/// Stores the embedded snippets as named dense and BM25 vectors, the latter of the code.
+struct SnippetSink<'a> {
+    inner: QdrantSink<'a>,
+}
+
+impl PointSink for SnippetSink<'_> {
+    async fn store(&self, points: Vec<PointStruct>) -> Result<usize> {
+        let points = points
+            .into_iter()
+            .map(|mut point| {
+                let dense = match point.vectors.take().and_then(|v| v.vectors_options) {
+                    Some(VectorsOptions::Vector(Vector {
+                        vector: Some(vector::Vector::Dense(dense)),
+                        ..
+                    })) => dense.data,
+                    _ => anyhow::bail!("Snippet point without a dense vector"),
+                };
+                let code = point
+                    .payload
+                    .get("code")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| anyhow::anyhow!("Snippet point without code"))?;
+                let vectors = NamedVectors::default()
+                    .add_vector(DENSE_VECTOR, dense)
+                    .add_vector(SPARSE_VECTOR, bm25_document(code));
+                point.vectors = Some(Vectors::from(vectors));
+                Ok(point)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.inner.store(points).await
+    }
+}
+
+/// Fail unless the existing collection has dense vectors of the local model.
+///
+/// `site_search/snippets.py` creates the collection for `SNIPPET_ENCODER` embeddings, which
+/// revisions of this indexer can't be mixed with.
+async fn check_snippet_collection(client: &Qdrant) -> Result<()> {
+    let size = client
+        .collection_info(SNIPPET_COLLECTION_NAME)
+        .await?
+        .result
+        .and_then(|info| info.config?.params?.vectors_config)
+        .as_ref()
+        .and_then(dense_vector_size);
+    match size {
+        Some(LOCAL_DENSE_SIZE) => Ok(()),
+        Some(size) => anyhow::bail!(
+            "`{SNIPPET_COLLECTION_NAME}` has {size}-dimensional `{DENSE_VECTOR}` vectors, but \
+             {MODEL_PATH} embeds {LOCAL_DENSE_SIZE} dimensions. It was probably written by \
+             site_search/snippets.py with {SNIPPET_ENCODER}, delete it to index with index_snippets"
+        ),
+        None => anyhow::bail!(
+            "`{SNIPPET_COLLECTION_NAME}` has no `{DENSE_VECTOR}` vector, delete it to index with \
+             index_snippets"
+        ),
+    }
+}
+
+async fn ensure_snippet_collection(client: &Qdrant) -> Result<()> {
+    if client.collection_exists(SNIPPET_COLLECTION_NAME).await? {
+        return check_snippet_collection(client).await;
+    }
+    let mut vectors_config = VectorsConfigBuilder::default();
+    vectors_config.add_named_vector_params(
+        DENSE_VECTOR,
+        VectorParamsBuilder::new(LOCAL_DENSE_SIZE, Distance::Cosine),
+    );
+    let mut sparse_vectors_config = SparseVectorsConfigBuilder::default();
+    sparse_vectors_config.add_named_vector_params(
+        SPARSE_VECTOR,
+        SparseVectorParamsBuilder::default().modifier(Modifier::Idf),
+    );
+    client
+        .create_collection(
+            CreateCollectionBuilder::new(SNIPPET_COLLECTION_NAME)
+                .vectors_config(vectors_config)
+                .sparse_vectors_config(sparse_vectors_config),
+        )
+        .await?;
+
+    for field in TEXT_FIELDS {
+        client
+            .create_field_index(
+                CreateFieldIndexCollectionBuilder::new(
+                    SNIPPET_COLLECTION_NAME,
+                    *field,
+                    FieldType::Text,
+                )
+                .field_index_params(
+                    TextIndexParamsBuilder::new(TokenizerType::Word)
+                        .min_token_len(1)
+                        .max_token_len(20)
+                        .lowercase(true),
+                )
+                .wait(true),
+            )
+            .await?;
+    }
+    for field in KEYWORD_FIELDS {
+        client
+            .create_field_index(
+                CreateFieldIndexCollectionBuilder::new(
+                    SNIPPET_COLLECTION_NAME,
+                    *field,
+                    FieldType::Keyword,
+                )
+                .wait(true),
+            )
+            .await?;
+    }
+    client
+        .create_field_index(
+            CreateFieldIndexCollectionBuilder::new(
+                SNIPPET_COLLECTION_NAME,
+                "revision",
+                FieldType::Integer,
+            )
+            .wait(true),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Parse all markdown files below `dir` into snippets of `revision`.
+fn collect_snippets(
+    dir: &Path,
+    base_url: &str,
+    origin: &SnippetOrigin,
+    revision: i64,
+) -> Result<Vec<Snippet>> {
+    let mut snippets = vec![];
+    for path in markdown_files(dir)? {
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) => {
+                log::warn!("Skipping {}: {err}", path.display());
+                continue;
+            }
+        };
+        let url = page_url(base_url, dir, &path);
+        snippets.extend(extract_snippets(&content, &url, origin, revision));
+    }
+    Ok(snippets)
+}
+
+/// Make the pending `revision` visible to searches and delete all but the `keep` latest revisions.
+///
+/// Removing the pending marker is a single request, so searches switch from the previous
+/// revision to the new one at once. The previous revision is kept by default, as services
+/// cache the latest revision for a while.
+async fn swap_revision(client: &Qdrant, revision: i64, keep: i64) -> Result<()> {
+    client
+        .delete_payload(
+            DeletePayloadPointsBuilder::new(SNIPPET_COLLECTION_NAME, vec![PENDING_FIELD.into()])
+                .points_selector(Filter::must([Condition::matches("revision", revision)]))
+                .wait(true),
+        )
+        .await?;
+    client
+        .delete_points(
+            DeletePointsBuilder::new(SNIPPET_COLLECTION_NAME)
+                .points(Filter::must([Condition::range(
+                    "revision",
+                    Range {
+                        lt: Some((revision - keep + 1) as f64),
+                        ..Default::default()
+                    },
+                )]))
+                .wait(true),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Delete the points of revisions that were never swapped in, e.g. by a failed run.
+async fn delete_pending(client: &Qdrant) -> Result<()> {
+    client
+        .delete_points(
+            DeletePointsBuilder::new(SNIPPET_COLLECTION_NAME)
+                .points(Filter::must_not([Condition::is_empty(PENDING_FIELD)]))
+                .wait(true),
+        )
+        .await?;
+    Ok(())
+}
+
+#[main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let config = PipelineConfig::from_env();
+    let source_dir = env_or("SNIPPETS_SOURCE_DIR", "content".to_string());
+    let base_url = env_or("SNIPPETS_BASE_URL", "https://qdrant.tech/".to_string());
+    let keep = env_or("SNIPPETS_KEEP_REVISIONS", 2_i64).max(1);
+    let origin = SnippetOrigin {
+        package_name: env_or("SNIPPETS_PACKAGE_NAME", "qdrant-client".to_string()),
+        version: env_or("SNIPPETS_VERSION", "latest".to_string()),
+    };
+
+    let qdrant_url = get_qdrant_url();
+    let mut builder = Qdrant::from_url(&qdrant_url);
+    if let Ok(key) = std::env::var("QDRANT_API_KEY") {
+        builder = builder.api_key(key);
+    }
+    let qdrant_client = builder.build()?;
+    ensure_snippet_collection(&qdrant_client).await?;
+    delete_pending(&qdrant_client).await?;
+
+    let live_revision = latest_revision(&qdrant_client).await?;
+    let revision = live_revision + 1;
+    let mut snippets = collect_snippets(Path::new(&source_dir), &base_url, &origin, revision)?;
+    log::info!(
+        "{} snippets found in {source_dir}, writing revision {revision}",
+        snippets.len()
+    );
+    if snippets.is_empty() {
+        anyhow::bail!("No snippets found in {source_dir}, keeping the current revision");
+    }
+
+    let concurrency = env_or("SNIPPETS_DESCRIPTION_CONCURRENCY", 8_usize);
+    let max_retries = env_or("SNIPPETS_DESCRIPTION_RETRIES", 3_u32);
+    let described = match DescriptionMode::from_env()? {
+        DescriptionMode::None => {
+            describe_all(
+                &NoDescriptions,
+                &mut snippets,
+                concurrency,
+                max_retries,
+                config.report_interval,
+            )
+            .await
+        }
+        DescriptionMode::OpenAi => {
+            let generator = ChatCompletions::from_env()?
+                .context("SNIPPETS_DESCRIPTION_URL is required for generated descriptions")?;
+            describe_all(
+                &generator,
+                &mut snippets,
+                concurrency,
+                max_retries,
+                config.report_interval,
+            )
+            .await
+        }
+        DescriptionMode::Cached => {
+            let generator = CachedDescriptions::load(
+                &qdrant_client,
+                live_revision,
+                ChatCompletions::from_env()?,
+            )
+            .await?;
+            log::info!(
+                "{} descriptions cached from revision {live_revision}",
+                generator.len()
+            );
+            describe_all(
+                &generator,
+                &mut snippets,
+                concurrency,
+                max_retries,
+                config.report_interval,
+            )
+            .await
+        }
+    };
+    log::info!("{described} of {} snippets described", snippets.len());
+
+    let tokenizer = BertTokenizer::from_file_with_special_token_mapping(
+        VOCAB_PATH,
+        true,
+        false,
+        SPECIAL_TOKEN_PATH,
+    )
+    .unwrap();
+    let env = Arc::new(Environment::builder().build()?);
+    let session = SessionBuilder::new(&env)?.with_model_from_file(MODEL_PATH)?;
+
+    let total = snippets.len();
+    let records = snippets
+        .iter()
+        .map(|snippet| {
+            let mut payload = snippet.payload()?;
+            payload.insert(PENDING_FIELD.to_string(), true.into());
+            if snippet.description.is_some() {
+                payload.insert(CODE_HASH_FIELD.to_string(), snippet.code_hash().into());
+            }
+            Ok(EmbedRecord {
+                id: PointId::from(snippet.point_id()),
+                text: snippet.document(),
+                payload,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    run_pipeline(
+        &SnippetSink {
+            inner: QdrantSink {
+                client: &qdrant_client,
+                collection: SNIPPET_COLLECTION_NAME,
+                max_retries: config.max_retries,
+            },
+        },
+        &tokenizer,
+        &session,
+        records.into_iter(),
+        Some(total),
+        &config,
+        None,
+    )
+    .await?;
+
+    swap_revision(&qdrant_client, revision, keep).await?;
+    log::info!("Revision {revision} is live, older than the last {keep} revisions deleted");
+    Ok(())
+}
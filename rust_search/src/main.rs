@@ -1,31 +1,54 @@
+mod cache;
 mod common;
+mod facets;
+mod federated;
+mod format;
+mod grouping;
+mod prefix_query;
+mod prefix_store;
+mod race;
 mod sections;
+mod skills;
+mod snippet;
+mod snippets;
+mod spelling;
+mod synonyms;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::time::Instant;
 use std::{borrow::Cow, net::SocketAddr, sync::Arc};
 
+use crate::cache::{CacheConfig, HttpCache};
 use crate::common::{
-    get_embedding, get_qdrant_url, COLLECTION_NAME, MODEL_PATH, PREFIX_COLLECTION_NAME,
+    get_embeddings, get_qdrant_url, COLLECTION_NAME, MODEL_PATH, PREFIX_COLLECTION_NAME,
 };
+use crate::facets::{facet_counts, facet_limit, parse_facets, FacetCount, FACET_FIELDS};
+use crate::grouping::{
+    group_query, merge_groups, Hit, DEFAULT_SUPPORTING, GROUP_BY_URL, MAX_SUPPORTING,
+};
+use crate::prefix_query::{PrefixInput, PrefixQueryConfig};
+use crate::prefix_store::{get_prefix_store_path, PrefixStore};
+use crate::race::{race, RacePolicy};
+use crate::spelling::{get_vocabulary_path, Speller};
+use crate::synonyms::{SynonymEmbedding, SynonymStore};
 use actix_cors::Cors;
 use actix_web::{
     get,
     http::header::ContentType,
     main, middleware,
     web::{Data, Query},
-    App, HttpResponse, HttpServer,
+    App, HttpRequest, HttpResponse, HttpServer,
 };
-use futures::StreamExt;
 use ort::{Environment, Session, SessionBuilder};
 use qdrant_client::qdrant::point_id::PointIdOptions;
 use qdrant_client::qdrant::r#match::MatchValue;
 use qdrant_client::qdrant::value::Kind;
 use qdrant_client::qdrant::{
-    BatchResult, Condition, Filter, LookupLocationBuilder, PointId, QueryBatchPointsBuilder,
-    QueryPoints, QueryPointsBuilder, RecommendInput, ScoredPoint, Value,
+    BatchResult, Condition, Filter, Fusion, GetPointsBuilder, LookupLocationBuilder, PointId,
+    PrefetchQueryBuilder, Query as QdrantQuery, QueryBatchPointsBuilder, QueryPoints, QueryPointsBuilder,
+    RecommendInput, ScoredPoint, Value,
 };
-use qdrant_client::Qdrant;
+use qdrant_client::{Qdrant, QdrantError};
 use rust_tokenizers::tokenizer::BertTokenizer;
 use serde::{Deserialize, Serialize};
 
@@ -63,17 +86,6 @@ fn post_process_response_text(text: &str, query: &str) -> String {
     highlighted_text.to_string()
 }
 
-fn prefix_to_id(prefix: &str) -> PointId {
-    let len = prefix.len();
-    PointId::from(u64::from_le_bytes(if len < 8 {
-        let mut result = [0_u8; 8];
-        result[..len].copy_from_slice(prefix.as_bytes());
-        result
-    } else {
-        prefix.as_bytes()[..8].try_into().unwrap()
-    }))
-}
-
 #[derive(Deserialize)]
 struct Search {
     q: String,
@@ -81,17 +93,33 @@ struct Search {
     section: String,
     #[serde(default)]
     partition: Option<String>,
+    /// `url` for one result per page
+    #[serde(default)]
+    group_by: Option<String>,
+    /// Other hits of the page per grouped result
+    #[serde(default)]
+    supporting: Option<u64>,
+    /// Comma-separated fields to count the text matches by, see `FACET_FIELDS`
+    #[serde(default)]
+    facets: Option<String>,
 }
 
 #[derive(Serialize)]
 struct ResponseItem {
     pub payload: HashMap<String, Value>,
     pub highlight: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supporting: Option<Vec<ResponseItem>>,
 }
 
 #[derive(Serialize)]
 struct Response {
     pub result: Vec<ResponseItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facets: Option<BTreeMap<&'static str, Vec<FacetCount>>>,
+    /// Spelling correction of the query, also used for the text matching
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub did_you_mean: Option<String>,
     pub time: f64,
 }
 
@@ -118,31 +146,55 @@ fn get_list_of_body_tags() -> Vec<String> {
     vec!["p".to_string(), "li".to_string()]
 }
 
-fn get_title_text_filter(query: &str) -> Vec<Condition> {
+/// Text match of any of the query variants, e.g. the query and its spelling correction
+fn get_text_condition(variants: &[String]) -> Condition {
+    let mut conditions: Vec<Condition> = variants
+        .iter()
+        .map(|variant| Condition::matches("text", MatchValue::Text(variant.clone())))
+        .collect();
+    if conditions.len() == 1 {
+        conditions.remove(0)
+    } else {
+        Filter::should(conditions).into()
+    }
+}
+
+fn get_title_text_filter(text_condition: &Condition) -> Vec<Condition> {
     vec![
         Condition::matches("tag", get_list_of_title_tags()),
-        Condition::matches("text", MatchValue::Text(query.to_string())),
+        text_condition.clone(),
     ]
 }
 
-fn get_body_text_filter(query: &str) -> Vec<Condition> {
+fn get_body_text_filter(text_condition: &Condition) -> Vec<Condition> {
     vec![
         Condition::matches("tag", get_list_of_body_tags()),
-        Condition::matches("text", MatchValue::Text(query.to_string())),
+        text_condition.clone(),
     ]
 }
 
+/// Text match in header or body, the union of the first two levels
+fn get_text_match_filter(text_condition: &Condition) -> Vec<Condition> {
+    let mut tags = get_list_of_title_tags();
+    tags.extend(get_list_of_body_tags());
+    vec![Condition::matches("tag", tags), text_condition.clone()]
+}
+
 fn get_title_filter() -> Vec<Condition> {
     vec![Condition::matches("tag", get_list_of_title_tags())]
 }
 
 fn get_recommend_query(
-    query: &str,
+    prefix: &PrefixInput,
     conditions: impl IntoIterator<Item = Condition>,
 ) -> QueryPoints {
+    // With locally stored prefix vectors, there is nothing to look up
+    if let Some(vector) = prefix.local_vector() {
+        return get_search_query(&vector, conditions);
+    }
     QueryPointsBuilder::new(COLLECTION_NAME)
         .query(RecommendInput {
-            positive: vec![prefix_to_id(query).into()],
+            positive: prefix.positives(),
             ..Default::default()
         })
         .filter(Filter::must(conditions))
@@ -164,6 +216,52 @@ fn get_search_query(
         .build()
 }
 
+/// Search with several vectors, fused by reciprocal rank if there is more than one.
+fn get_multi_search_query(
+    vectors: &[Vec<f32>],
+    conditions: impl IntoIterator<Item = Condition>,
+) -> QueryPoints {
+    let filter = Filter::must(conditions);
+    if let [vector] = vectors {
+        return get_search_query(vector, filter.must);
+    }
+    let mut builder = QueryPointsBuilder::new(COLLECTION_NAME);
+    for vector in vectors {
+        builder = builder.add_prefetch(
+            PrefetchQueryBuilder::default()
+                .query(QdrantQuery::new_nearest(vector.clone()))
+                .limit(SEARCH_LIMIT),
+        );
+    }
+    builder
+        .query(QdrantQuery::new_fusion(Fusion::Rrf))
+        .filter(filter)
+        .limit(SEARCH_LIMIT)
+        .with_payload(true)
+        .build()
+}
+
+/// Embeddings of the query and its synonym variants, averaged unless searched separately.
+fn embed_queries(
+    tokenizer: &BertTokenizer,
+    session: &Session,
+    queries: &[String],
+    synonym_embedding: SynonymEmbedding,
+) -> anyhow::Result<Vec<Vec<f32>>> {
+    let texts: Vec<&str> = queries.iter().map(String::as_str).collect();
+    let vectors = get_embeddings(tokenizer, session, &texts)?;
+    if synonym_embedding == SynonymEmbedding::Multi || vectors.len() < 2 {
+        return Ok(vectors);
+    }
+    let mut mean = vec![0.0; vectors[0].len()];
+    for vector in &vectors {
+        for (mean, value) in mean.iter_mut().zip(vector) {
+            *mean += value / vectors.len() as f32;
+        }
+    }
+    Ok(vec![mean])
+}
+
 fn point_id_to_hash(id: PointId) -> String {
     match id.point_id_options {
         None => "".to_string(),
@@ -192,14 +290,43 @@ fn merge_results(results: Vec<BatchResult>) -> Vec<ScoredPoint> {
     res.into_iter().take(SEARCH_LIMIT as usize).collect()
 }
 
+/// Run the queries of the filtering tiers, grouped by page if `supporting` is set.
+async fn query_tiers(
+    client: &Qdrant,
+    queries: Vec<QueryPoints>,
+    supporting: Option<u64>,
+) -> Result<Vec<Hit>, QdrantError> {
+    let Some(supporting) = supporting else {
+        let response = client
+            .query_batch(QueryBatchPointsBuilder::new(COLLECTION_NAME, queries))
+            .await?;
+        log::debug!("Qdrant time: {:?}", response.time);
+        return Ok(merge_results(response.result)
+            .into_iter()
+            .map(Hit::from)
+            .collect());
+    };
+    let responses = futures::future::try_join_all(queries.into_iter().map(|query| {
+        client.query_groups(group_query(query, GROUP_BY_URL, supporting))
+    }))
+    .await?;
+    let groups = responses
+        .into_iter()
+        .map(|response| response.result.map(|r| r.groups).unwrap_or_default())
+        .collect();
+    Ok(merge_groups(groups, SEARCH_LIMIT as usize))
+}
+
 async fn recommend_request(
     client: &Qdrant,
     section_condition: Option<Condition>,
     partition_condition: Option<Condition>,
-    query: &str,
-) -> Result<Vec<ScoredPoint>, HttpResponse> {
-    let mut title_text_filter = get_title_text_filter(query);
-    let mut body_text_filter = get_body_text_filter(query);
+    mut prefix: PrefixInput,
+    text_condition: &Condition,
+    supporting: Option<u64>,
+) -> Result<Vec<Hit>, HttpResponse> {
+    let mut title_text_filter = get_title_text_filter(text_condition);
+    let mut body_text_filter = get_body_text_filter(text_condition);
     let mut title_filter = get_title_filter();
     let mut no_text_filter = vec![];
 
@@ -217,38 +344,63 @@ async fn recommend_request(
         no_text_filter.push(partition_condition);
     }
 
-    match client
-        .query_batch(QueryBatchPointsBuilder::new(
-            COLLECTION_NAME,
-            vec![
-                get_recommend_query(query, title_text_filter),
-                get_recommend_query(query, body_text_filter),
-                get_recommend_query(query, title_filter),
-                get_recommend_query(query, no_text_filter),
-            ],
+    let queries = |prefix: &PrefixInput| {
+        vec![
+            get_recommend_query(prefix, title_text_filter.clone()),
+            get_recommend_query(prefix, body_text_filter.clone()),
+            get_recommend_query(prefix, title_filter.clone()),
+            get_recommend_query(prefix, no_text_filter.clone()),
+        ]
+    };
+    let internal_error = |e: QdrantError| HttpResponse::InternalServerError().body(e.to_string());
+    if prefix.positives().is_empty() {
+        return Ok(vec![]);
+    }
+    let err = match query_tiers(client, queries(&prefix), supporting).await {
+        Ok(hits) => return Ok(hits),
+        Err(err) if is_missing_point(&err) => err,
+        Err(err) => return Err(internal_error(err)),
+    };
+    // A prefix missing from `prefix-cache` fails the whole recommendation, retry without it
+    if prefix.ids.len() < 2 {
+        log::debug!("Recommendation failed: {}", err);
+        return Ok(vec![]);
+    }
+    let known: Vec<PointId> = client
+        .get_points(GetPointsBuilder::new(
+            PREFIX_COLLECTION_NAME,
+            prefix.ids.clone(),
         ))
         .await
-    {
-        Ok(response) => {
-            log::debug!("Recommend Qdrant time: {:?}", response.time);
-            Ok(merge_results(response.result))
-        }
-        Err(_) => {
-            // TODO: distinguish between 404 and other errors
-            Ok(vec![])
-        }
+        .map_err(internal_error)?
+        .result
+        .into_iter()
+        .filter_map(|p| p.id)
+        .collect();
+    prefix.retain_ids(&known);
+    if prefix.positives().is_empty() {
+        return Ok(vec![]);
     }
+    query_tiers(client, queries(&prefix), supporting)
+        .await
+        .map_err(internal_error)
+}
+
+/// Whether `err` is the answer to a recommendation from a point that doesn't exist.
+fn is_missing_point(err: &QdrantError) -> bool {
+    matches!(err, QdrantError::ResponseError { status } if status.code() == tonic::Code::NotFound)
 }
 
 async fn search_request(
     client: &Qdrant,
     section_condition: Option<Condition>,
     partition_condition: Option<Condition>,
-    query: &str,
-    vector: Vec<f32>,
-) -> Result<Vec<ScoredPoint>, HttpResponse> {
-    let mut title_text_filter = get_title_text_filter(query);
-    let mut body_text_filter = get_body_text_filter(query);
+    text_condition: &Condition,
+    vectors: Vec<Vec<f32>>,
+    supporting: Option<u64>,
+) -> Result<Vec<Hit>, HttpResponse> {
+    let mut title_text_filter = get_title_text_filter(text_condition);
+    let mut body_text_filter = get_body_text_filter(text_condition);
     let mut title_filter = get_title_filter();
     let mut no_text_filter = vec![];
 
@@ -266,66 +418,128 @@ async fn search_request(
         no_text_filter.push(partition_condition);
     }
 
-    match client
-        .query_batch(QueryBatchPointsBuilder::new(
-            COLLECTION_NAME,
-            vec![
-                get_search_query(&vector, title_text_filter),
-                get_search_query(&vector, body_text_filter),
-                get_search_query(&vector, title_filter),
-                get_search_query(&vector, no_text_filter),
-            ],
-        ))
+    let queries = vec![
+        get_multi_search_query(&vectors, title_text_filter),
+        get_multi_search_query(&vectors, body_text_filter),
+        get_multi_search_query(&vectors, title_filter),
+        get_multi_search_query(&vectors, no_text_filter),
+    ];
+    query_tiers(client, queries, supporting)
         .await
-    {
-        Ok(response) => {
-            log::debug!("Search Qdrant time: {:?}", response.time);
-            Ok(merge_results(response.result))
-        }
-        Err(e) => Err(HttpResponse::InternalServerError().body(e.to_string())),
-    }
+        .map_err(|e| HttpResponse::InternalServerError().body(e.to_string()))
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn search_or_recommend(
     client: &Qdrant,
     tokenizer: &BertTokenizer,
     session: &Session,
+    prefix_store: Option<&PrefixStore>,
     section_condition: Option<Condition>,
     partition_condition: Option<Condition>,
-    query: &str,
-    do_recommend: bool,
-) -> Result<Vec<ScoredPoint>, HttpResponse> {
-    if do_recommend {
-        recommend_request(client, section_condition, partition_condition, query).await
+    prefix_tokens: Option<&[String]>,
+    text_condition: &Condition,
+    embedding_queries: &[String],
+    synonym_embedding: SynonymEmbedding,
+    supporting: Option<u64>,
+) -> Result<Vec<Hit>, HttpResponse> {
+    if let Some(prefix_tokens) = prefix_tokens {
+        let prefix = PrefixInput::resolve(prefix_tokens, prefix_store, |texts| {
+            get_embeddings(tokenizer, session, texts)
+        })
+        .map_err(|e| HttpResponse::InternalServerError().body(e.to_string()))?;
+        recommend_request(
+            client,
+            section_condition,
+            partition_condition,
+            prefix,
+            text_condition,
+            supporting,
+        )
+        .await
     } else {
-        let vector = get_embedding(tokenizer, session, query);
+        let vectors = embed_queries(tokenizer, session, embedding_queries, synonym_embedding)
+            .map_err(|e| HttpResponse::InternalServerError().body(e.to_string()))?;
         search_request(
             client,
             section_condition,
             partition_condition,
-            query,
-            vector,
+            text_condition,
+            vectors,
+            supporting,
         )
         .await
     }
 }
 
 #[get("/api/search")]
+#[allow(clippy::too_many_arguments)]
 async fn query_handler(
+    req: HttpRequest,
     context: Data<(BertTokenizer, Session, Qdrant)>,
+    prefix_store: Data<Option<PrefixStore>>,
+    prefix_config: Data<PrefixQueryConfig>,
+    race_policy: Data<RacePolicy>,
+    speller: Data<Option<Speller>>,
+    synonym_store: Data<SynonymStore>,
+    cache: Data<HttpCache>,
     search: Query<Search>,
 ) -> HttpResponse {
     let time_start = Instant::now();
 
+    let cache_control = &cache.config.search_cache_control;
+    // results also change with the synonyms and the spelling vocabulary
+    let validators = cache.validators(COLLECTION_NAME, &req).await.map(|validators| {
+        let synonyms_modified = synonym_store.version();
+        // weak, as the body has the search time and the race can return either side's hits
+        let validators = validators
+            .weak()
+            .depending_on(&format!("synonyms:{synonyms_modified:?}"), synonyms_modified);
+        match speller.get_ref() {
+            Some(speller) => validators.depending_on(
+                &format!("vocabulary:{}", speller.version()),
+                speller.modified(),
+            ),
+            None => validators,
+        }
+    });
+    if let Some(validators) = validators.as_ref().filter(|v| v.is_fresh(&req)) {
+        return validators.not_modified(cache_control);
+    }
+
     let Search {
         q,
         section,
         partition,
+        group_by,
+        supporting,
+        facets,
     } = search.into_inner();
 
     log::info!("Query: {}", q);
 
+    let supporting = match group_by.as_deref() {
+        None => None,
+        Some(GROUP_BY_URL) => Some(supporting.unwrap_or(DEFAULT_SUPPORTING).min(MAX_SUPPORTING)),
+        Some(other) => {
+            return HttpResponse::BadRequest()
+                .body(format!("Unsupported group_by `{other}`, expected `{GROUP_BY_URL}`"))
+        }
+    };
+
+    let facet_fields = match facets.as_deref().map(parse_facets) {
+        None => None,
+        Some(Ok(fields)) => Some(fields),
+        Some(Err(field)) => {
+            return HttpResponse::BadRequest().body(format!(
+                "Unsupported facet `{field}`, expected one of {}",
+                FACET_FIELDS.join(", ")
+            ))
+        }
+    };
+
     let (tokenizer, session, qdrant) = context.get_ref();
+    let prefix_store = prefix_store.get_ref().as_ref();
 
     let section_condition = if section.is_empty() {
         None
@@ -347,68 +561,130 @@ async fn query_handler(
         }
     });
 
-    let mut query_stream = vec![];
+    let did_you_mean = speller.get_ref().as_ref().and_then(|speller| speller.correct(&q));
+    if let Some(corrected) = &did_you_mean {
+        log::info!("Did you mean: {}", corrected);
+    }
+    let synonyms = synonym_store.get();
+    let mut text_variants = vec![q.clone()];
+    text_variants.extend(did_you_mean.clone());
+    let corrected = did_you_mean.as_deref().unwrap_or(&q);
+    text_variants.extend(synonyms.expand(corrected, synonym_store.max_variants));
+    let mut seen = HashSet::new();
+    text_variants.retain(|variant| seen.insert(variant.to_lowercase()));
+    let mut embedding_queries = vec![q.clone()];
+    if synonym_store.embedding != SynonymEmbedding::None {
+        embedding_queries.extend(synonyms.expand(&q, synonym_store.max_variants));
+    }
+    if text_variants.len() > 1 {
+        log::debug!("Query variants: {:?}", text_variants);
+    }
+    let text_condition = get_text_condition(&text_variants);
+
+    let mut facet_filter = get_text_match_filter(&text_condition);
+    facet_filter.extend(section_condition.clone());
+    facet_filter.extend(partition_condition.clone());
+    let facets = async {
+        let Some(fields) = &facet_fields else {
+            return Ok(None);
+        };
+        facet_counts(qdrant, fields, Filter::must(facet_filter), facet_limit())
+            .await
+            .map(Some)
+    };
 
-    if q.len() < 5 {
-        query_stream.push(search_or_recommend(
+    // Queries of a few short tokens are likely typed prefixes of words
+    let prefix_tokens = prefix_config.prefix_tokens(&q);
+    let recommend = prefix_tokens.as_deref().map(|prefix_tokens| {
+        search_or_recommend(
             qdrant,
             tokenizer,
             session,
+            prefix_store,
             section_condition.clone(),
             partition_condition.clone(),
-            &q,
-            true,
-        ));
-    }
-
-    query_stream.push(search_or_recommend(
+            Some(prefix_tokens),
+            &text_condition,
+            &embedding_queries,
+            synonym_store.embedding,
+            supporting,
+        )
+    });
+    let embedding_search = search_or_recommend(
         qdrant,
         tokenizer,
         session,
+        prefix_store,
         section_condition.clone(),
         partition_condition.clone(),
-        &q,
-        false,
-    ));
-
-    let mut search_stream = futures::stream::iter(query_stream).buffer_unordered(2);
-
-    let mut points = vec![];
-    while let Some(result) = search_stream.next().await {
-        log::debug!("response in {:?}", time_start.elapsed());
-        match result {
-            Ok(response) => {
-                if !response.is_empty() {
-                    points.extend(response);
-                    break;
-                }
-            }
-            Err(err) => return err,
-        }
-    }
+        None,
+        &text_condition,
+        &embedding_queries,
+        synonym_store.embedding,
+        supporting,
+    );
+
+    let policy = *race_policy.get_ref();
+    let search = async {
+        let (hits, outcome) = race(
+            policy,
+            &q,
+            recommend,
+            embedding_search,
+            |hit: &Hit| hit.point.id.clone().map(point_id_to_hash).unwrap_or_default(),
+            SEARCH_LIMIT as usize,
+        )
+        .await?;
+        log::info!("Race {:?}: {:?} in {:?}", policy, outcome, time_start.elapsed());
+        Ok::<_, HttpResponse>(hits)
+    };
+
+    let (hits, facets) = futures::join!(search, facets);
+    let hits = match hits {
+        Ok(hits) => hits,
+        Err(err) => return err,
+    };
+    // counts are an extra, e.g. a field without keyword index can't be counted
+    let facets = facets.unwrap_or_else(|e| {
+        log::error!("Facet counts failed: {e}");
+        None
+    });
 
     // Postprocess search results
-    let response_items: Vec<_> = points
+    let to_item = |point: ScoredPoint| {
+        let highlight = if let Some(Kind::StringValue(text)) =
+            &point.payload.get("text").and_then(|v| v.kind.as_ref())
+        {
+            post_process_response_text(text, did_you_mean.as_deref().unwrap_or(&q))
+        } else {
+            "".to_string()
+        };
+
+        ResponseItem {
+            payload: point.payload,
+            highlight,
+            supporting: None,
+        }
+    };
+    let response_items: Vec<_> = hits
         .into_iter()
-        .map(|point| {
-            let highlight = if let Some(Kind::StringValue(text)) =
-                &point.payload.get("text").and_then(|v| v.kind.as_ref())
-            {
-                post_process_response_text(text, &q)
-            } else {
-                "".to_string()
-            };
-
-            ResponseItem {
-                payload: point.payload,
-                highlight,
-            }
+        .map(|hit| ResponseItem {
+            supporting: hit
+                .supporting
+                .map(|points| points.into_iter().map(to_item).collect()),
+            ..to_item(hit.point)
         })
         .collect();
 
-    HttpResponse::Ok().insert_header(ContentType::json()).body(
+    let mut response = HttpResponse::Ok();
+    if let Some(validators) = &validators {
+        validators.apply(&mut response, cache_control);
+    }
+    response.insert_header(ContentType::json()).body(
         serde_json::to_string(&Response {
             result: response_items,
+            facets,
+            did_you_mean,
             time: time_start.elapsed().as_micros() as f64 / 1_000_000.0,
         })
         .expect("Failed to serialize response"),
@@ -442,9 +718,42 @@ async fn main() -> std::io::Result<()> {
         builder = builder.api_key(key.clone());
     }
     let qdrant = builder.build().unwrap();
+    let section_ranker = Data::new(sections::SectionRanker::from_env(&env).unwrap());
+    let prefix_store_path = get_prefix_store_path();
+    let prefix_store = if std::path::Path::new(&prefix_store_path).exists() {
+        let store = PrefixStore::open(&prefix_store_path).unwrap();
+        log::info!(
+            "Loaded {} prefix vectors from {}, other prefixes are embedded",
+            store.len(),
+            prefix_store_path
+        );
+        Some(store)
+    } else {
+        log::info!("No prefix store at {}, using {}", prefix_store_path, PREFIX_COLLECTION_NAME);
+        None
+    };
+    let prefix_store = Data::new(prefix_store);
+    let prefix_config = Data::new(PrefixQueryConfig::from_env());
+    let race_policy = Data::new(RacePolicy::from_env().unwrap());
+    let vocabulary_path = get_vocabulary_path();
+    let speller = if std::path::Path::new(&vocabulary_path).exists() {
+        let min_count = common::env_or("SPELLING_MIN_COUNT", 2);
+        let speller = Speller::open(&vocabulary_path, min_count).unwrap();
+        log::info!("Loaded {} words for spelling correction from {}", speller.len(), vocabulary_path);
+        Some(speller)
+    } else {
+        log::info!("No vocabulary at {}, spelling correction disabled", vocabulary_path);
+        None
+    };
+    let speller = Data::new(speller);
+    let synonym_store = Data::new(SynonymStore::from_env().unwrap());
     qdrant.health_check().await.unwrap();
+    sections::ensure_line_index(&qdrant).await.unwrap();
     let qdrant = Data::new(qdrant);
     let context = Data::new((tokenizer, session, qdrant.get_ref().clone()));
+    let cache = Data::new(HttpCache::new(qdrant.get_ref().clone(), CacheConfig::from_env()));
+    let snippet_revisions = Data::new(snippets::RevisionCache::from_env());
+    let federated_sources = Data::new(federated::FederatedSources::from_env().unwrap());
     let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
@@ -454,10 +763,24 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .app_data(context.clone())
             .app_data(qdrant.clone())
+            .app_data(prefix_store.clone())
+            .app_data(prefix_config.clone())
+            .app_data(race_policy.clone())
+            .app_data(speller.clone())
+            .app_data(synonym_store.clone())
+            .app_data(section_ranker.clone())
+            .app_data(cache.clone())
+            .app_data(snippet_revisions.clone())
+            .app_data(federated_sources.clone())
             .wrap(cors)
             .wrap(middleware::Logger::default())
             .service(query_handler)
             .service(sections::md_handler)
+            .service(sections::llms_txt)
+            .service(sections::llms_full_txt)
+            .service(skills::skills_handler)
+            .service(snippets::snippets_handler)
+            .service(federated::federated_handler)
     });
     server.bind(addr)?.run().await
 }
@@ -0,0 +1,196 @@
+//! Prefix recommendations for short queries, e.g. "hn sea" while typing "hnsw search".
+//!
+//! Every token of a short query is a prefix. With a local prefix store, their vectors are
+//! taken from it, or embedded by the service for prefixes it doesn't have, so `prefix-cache`
+//! isn't needed. Without one, they are recommended by id from `prefix-cache`.
+
+use qdrant_client::qdrant::{PointId, VectorInput};
+
+use crate::common::{env_or, prefix_to_id};
+use crate::prefix_store::PrefixStore;
+
+/// Which queries are answered from prefix vectors.
+#[derive(Debug, Clone)]
+pub struct PrefixQueryConfig {
+    /// Most tokens of a prefix query (`PREFIX_QUERY_MAX_TOKENS`)
+    pub max_tokens: usize,
+    /// Longest token of a prefix query in characters (`PREFIX_QUERY_MAX_TOKEN_LEN`)
+    pub max_token_len: usize,
+}
+
+impl PrefixQueryConfig {
+    pub fn from_env() -> Self {
+        Self {
+            max_tokens: env_or("PREFIX_QUERY_MAX_TOKENS", 3),
+            max_token_len: env_or("PREFIX_QUERY_MAX_TOKEN_LEN", 4),
+        }
+    }
+
+    /// The lowercase tokens of `query` if all of them are short enough to be prefixes.
+    pub fn prefix_tokens(&self, query: &str) -> Option<Vec<String>> {
+        let tokens: Vec<String> = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|token| !token.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        let short = tokens
+            .iter()
+            .all(|token| token.chars().count() <= self.max_token_len);
+        (!tokens.is_empty() && tokens.len() <= self.max_tokens && short).then_some(tokens)
+    }
+}
+
+/// The prefix vectors of the tokens of a query.
+#[derive(Debug, Default, PartialEq)]
+pub struct PrefixInput {
+    /// Vectors of the local prefix store, or embedded by the service
+    pub vectors: Vec<Vec<f32>>,
+    /// Prefixes to be looked up in `prefix-cache`, without a local store
+    pub ids: Vec<PointId>,
+}
+
+impl PrefixInput {
+    /// Look up `tokens` in `prefix_store`, embedding the missing ones with `embed`.
+    pub fn resolve(
+        tokens: &[String],
+        prefix_store: Option<&PrefixStore>,
+        embed: impl FnOnce(&[&str]) -> anyhow::Result<Vec<Vec<f32>>>,
+    ) -> anyhow::Result<Self> {
+        let Some(store) = prefix_store else {
+            return Ok(Self {
+                vectors: vec![],
+                ids: tokens.iter().filter_map(|token| prefix_to_id(token)).collect(),
+            });
+        };
+        let mut input = Self::default();
+        let mut missing = vec![];
+        for token in tokens {
+            match store.get(token) {
+                Some(vector) => input.vectors.push(vector),
+                None => missing.push(token.as_str()),
+            }
+        }
+        if !missing.is_empty() {
+            input.vectors.extend(embed(&missing)?);
+        }
+        Ok(input)
+    }
+
+    /// Drop the prefixes to be looked up that are not among the `known` ids of `prefix-cache`.
+    pub fn retain_ids(&mut self, known: &[PointId]) {
+        self.ids.retain(|id| known.contains(id));
+    }
+
+    /// The mean of the prefix vectors, if all of them are stored locally.
+    pub fn local_vector(&self) -> Option<Vec<f32>> {
+        if !self.ids.is_empty() || self.vectors.is_empty() {
+            return None;
+        }
+        let mut mean = vec![0.0; self.vectors[0].len()];
+        for vector in &self.vectors {
+            for (mean, value) in mean.iter_mut().zip(vector) {
+                *mean += value / self.vectors.len() as f32;
+            }
+        }
+        Some(mean)
+    }
+
+    /// Positive examples of a recommendation, which averages them.
+    pub fn positives(&self) -> Vec<VectorInput> {
+        self.ids
+            .iter()
+            .cloned()
+            .map(VectorInput::new_id)
+            .chain(self.vectors.iter().cloned().map(VectorInput::new_dense))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> PrefixQueryConfig {
+        PrefixQueryConfig {
+            max_tokens: 3,
+            max_token_len: 4,
+        }
+    }
+
+    #[test]
+    fn short_tokens_are_prefixes() {
+        let config = config();
+        assert_eq!(
+            config.prefix_tokens("HN sea"),
+            Some(vec!["hn".to_string(), "sea".to_string()])
+        );
+        assert_eq!(config.prefix_tokens("qdr"), Some(vec!["qdr".to_string()]));
+        assert_eq!(config.prefix_tokens("hnsw index"), None);
+        assert_eq!(config.prefix_tokens("a b c d"), None);
+        assert_eq!(config.prefix_tokens(" - "), None);
+    }
+
+    fn id(prefix: &str) -> PointId {
+        prefix_to_id(prefix).unwrap()
+    }
+
+    #[test]
+    fn prefixes_without_id_are_unknown() {
+        let tokens = ["hn".to_string(), "поиск".to_string()];
+        let input = PrefixInput::resolve(&tokens, None, |_| unreachable!()).unwrap();
+        assert_eq!(input.ids, vec![id("hn")]);
+    }
+
+    #[test]
+    fn stored_vectors_are_averaged() {
+        let input = PrefixInput {
+            vectors: vec![vec![1.0, 0.0], vec![0.0, 1.0]],
+            ids: vec![],
+        };
+        assert_eq!(input.local_vector(), Some(vec![0.5, 0.5]));
+        assert_eq!(input.positives().len(), 2);
+
+        let input = PrefixInput {
+            vectors: vec![vec![1.0, 0.0]],
+            ids: vec![id("sea")],
+        };
+        assert_eq!(input.local_vector(), None);
+        assert_eq!(input.positives().len(), 2);
+
+        let tokens = ["hn".to_string(), "sea".to_string()];
+        let input = PrefixInput::resolve(&tokens, None, |_| unreachable!()).unwrap();
+        assert_eq!(input.ids, vec![id("hn"), id("sea")]);
+    }
+
+    #[test]
+    fn unknown_prefixes_are_dropped() {
+        let tokens = ["hn".to_string(), "xq".to_string()];
+        let mut input = PrefixInput::resolve(&tokens, None, |_| unreachable!()).unwrap();
+        assert_eq!(input.positives().len(), 2);
+
+        input.retain_ids(&[id("hn"), id("sea")]);
+        assert_eq!(input.ids, vec![id("hn")]);
+        assert_eq!(input.positives().len(), 1);
+
+        input.retain_ids(&[]);
+        assert!(input.positives().is_empty());
+    }
+
+    #[test]
+    fn missing_prefixes_are_embedded() {
+        let path = std::env::temp_dir().join(format!("prefix-query-{}.bin", std::process::id()));
+        let vectors = std::collections::BTreeMap::from([("hn".to_string(), vec![1.0, 0.0])]);
+        crate::prefix_store::write_prefix_store(&path, &vectors).unwrap();
+        let store = PrefixStore::open(&path).unwrap();
+
+        let tokens = ["hn".to_string(), "sea".to_string()];
+        let input = PrefixInput::resolve(&tokens, Some(&store), |texts| {
+            assert_eq!(texts, ["sea"]);
+            Ok(vec![vec![0.0, 1.0]])
+        })
+        .unwrap();
+        assert!(input.ids.is_empty());
+        assert_eq!(input.local_vector(), Some(vec![0.5, 0.5]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,224 @@
+//! Local, memory-mapped store of prefix vectors.
+//!
+//! The service can resolve short queries against this file instead of looking up the
+//! prefix vector in the `prefix-cache` collection on every keystroke.
+//!
+//! File layout (little endian):
+//!
+//! | field   | size                    |
+//! |---------|-------------------------|
+//! | magic   | 4 bytes, `PFXV`         |
+//! | version | u32                     |
+//! | dim     | u32                     |
+//! | count   | u32                     |
+//! | fst_len | u64                     |
+//! | fst     | `fst_len` bytes         |
+//! | vectors | `count * (4 + dim)` bytes, each an f32 scale followed by `dim` i8 values |
+//!
+//! The FST maps every prefix to the index of its vector.
+
+// Allow unused code, as the indexer only writes and the service only reads the store
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use fst::{Map, MapBuilder};
+use memmap2::Mmap;
+
+const MAGIC: &[u8; 4] = b"PFXV";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 24;
+
+pub const PREFIX_STORE_PATH: &str = "prefix-store.bin";
+
+pub fn get_prefix_store_path() -> String {
+    std::env::var("PREFIX_STORE_PATH").unwrap_or_else(|_| PREFIX_STORE_PATH.to_string())
+}
+
+/// Symmetric int8 quantization, returns the scale and the quantized values.
+fn quantize(vector: &[f32]) -> (f32, Vec<i8>) {
+    let max = vector.iter().fold(0.0_f32, |max, v| max.max(v.abs()));
+    if max == 0.0 {
+        return (0.0, vec![0; vector.len()]);
+    }
+    let scale = max / i8::MAX as f32;
+    let values = vector
+        .iter()
+        .map(|v| (v / scale).round().clamp(i8::MIN as f32, i8::MAX as f32) as i8)
+        .collect();
+    (scale, values)
+}
+
+fn dequantize(scale: f32, values: &[u8]) -> Vec<f32> {
+    values.iter().map(|v| *v as i8 as f32 * scale).collect()
+}
+
+/// Write `vectors` to a prefix store at `path`.
+///
+/// The store is written to a temporary file that then replaces `path`, so a service that has
+/// the previous store mapped keeps reading it until it is restarted.
+pub fn write_prefix_store(
+    path: impl AsRef<Path>,
+    vectors: &BTreeMap<String, Vec<f32>>,
+) -> anyhow::Result<()> {
+    let dim = vectors.values().next().map_or(0, Vec::len);
+    let mut fst = MapBuilder::memory();
+    for (idx, (prefix, vector)) in vectors.iter().enumerate() {
+        if vector.len() != dim {
+            bail!(
+                "Vector of `{prefix}` has {} dimensions, expected {dim}",
+                vector.len()
+            );
+        }
+        fst.insert(prefix, idx as u64)?;
+    }
+    let fst = fst.into_inner()?;
+
+    let path = path.as_ref();
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(dim as u32).to_le_bytes())?;
+    writer.write_all(&(vectors.len() as u32).to_le_bytes())?;
+    writer.write_all(&(fst.len() as u64).to_le_bytes())?;
+    writer.write_all(&fst)?;
+    for vector in vectors.values() {
+        let (scale, values) = quantize(vector);
+        writer.write_all(&scale.to_le_bytes())?;
+        writer.write_all(&values.iter().map(|v| *v as u8).collect::<Vec<_>>())?;
+    }
+    writer.into_inner()?.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// A range of the memory-mapped file, so the FST can be read in place.
+struct MmapSlice {
+    mmap: Arc<Mmap>,
+    range: Range<usize>,
+}
+
+impl AsRef<[u8]> for MmapSlice {
+    fn as_ref(&self) -> &[u8] {
+        &self.mmap[self.range.clone()]
+    }
+}
+
+/// Read-only view of a prefix store file.
+pub struct PrefixStore {
+    mmap: Arc<Mmap>,
+    fst: Map<MmapSlice>,
+    dim: usize,
+    count: usize,
+    vectors_offset: usize,
+}
+
+impl PrefixStore {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        // Safety: the indexer never writes to an existing store, it renames a new file over
+        // it, so the mapped file doesn't change
+        let mmap = Arc::new(unsafe { Mmap::map(&file)? });
+        if mmap.len() < HEADER_LEN || &mmap[..4] != MAGIC {
+            bail!("{} is not a prefix store", path.display());
+        }
+        let read_u32 = |at: usize| u32::from_le_bytes(mmap[at..at + 4].try_into().unwrap());
+        let version = read_u32(4);
+        if version != VERSION {
+            bail!("Unsupported prefix store version {version}");
+        }
+        let dim = read_u32(8) as usize;
+        let count = read_u32(12) as usize;
+        let fst_len = u64::from_le_bytes(mmap[16..24].try_into().unwrap()) as usize;
+        let vectors_offset = HEADER_LEN + fst_len;
+        if mmap.len() != vectors_offset + count * (4 + dim) {
+            bail!("Prefix store {} is truncated", path.display());
+        }
+        let fst = Map::new(MmapSlice {
+            mmap: mmap.clone(),
+            range: HEADER_LEN..vectors_offset,
+        })
+        .with_context(|| format!("Malformed prefix index in {}", path.display()))?;
+        Ok(Self {
+            mmap,
+            fst,
+            dim,
+            count,
+            vectors_offset,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Dequantized vector of `prefix`, if it is in the store.
+    pub fn get(&self, prefix: &str) -> Option<Vec<f32>> {
+        let idx = self.fst.get(prefix)? as usize;
+        let start = self.vectors_offset + idx * (4 + self.dim);
+        let scale = f32::from_le_bytes(self.mmap[start..start + 4].try_into().unwrap());
+        Some(dequantize(
+            scale,
+            &self.mmap[start + 4..start + 4 + self.dim],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantization_roundtrip() {
+        let vector = vec![0.5, -1.0, 0.25, 0.0];
+        let (scale, values) = quantize(&vector);
+        let restored = dequantize(scale, &values.iter().map(|v| *v as u8).collect::<Vec<_>>());
+        for (a, b) in vector.iter().zip(restored) {
+            assert!((a - b).abs() < 0.01, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    fn write_and_read_store() {
+        let path = std::env::temp_dir().join(format!("prefix-store-{}.bin", std::process::id()));
+        let vectors = BTreeMap::from([
+            ("hn".to_string(), vec![0.1, 0.2, -0.3]),
+            ("qd".to_string(), vec![1.0, 0.0, 0.5]),
+            ("qdr".to_string(), vec![0.0, 0.0, 0.0]),
+        ]);
+        write_prefix_store(&path, &vectors).unwrap();
+
+        let store = PrefixStore::open(&path).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.dim(), 3);
+        let qd = store.get("qd").unwrap();
+        assert!((qd[0] - 1.0).abs() < 0.01 && (qd[2] - 0.5).abs() < 0.01);
+        assert_eq!(store.get("qdr").unwrap(), vec![0.0, 0.0, 0.0]);
+        assert!(store.get("q").is_none());
+
+        // rewriting the store leaves the mapped one intact
+        let vectors = BTreeMap::from([("hn".to_string(), vec![0.0, 1.0])]);
+        write_prefix_store(&path, &vectors).unwrap();
+        assert_eq!(store.len(), 3);
+        assert!((store.get("qd").unwrap()[0] - 1.0).abs() < 0.01);
+        assert_eq!(PrefixStore::open(&path).unwrap().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
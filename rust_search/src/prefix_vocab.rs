@@ -0,0 +1,202 @@
+//! Prefix vocabulary for the prefix cache.
+//!
+//! Prefixes come either from a plain word list or from the words of the indexed corpus,
+//! in which case every prefix is weighted by how often the words it starts occur.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::Context;
+
+use crate::common::{env_or, prefix_to_num, PREFIX_ID_MAX_BYTES};
+
+/// Where the words for the prefixes are taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VocabSource {
+    /// External word list (`words.txt`), one word per line
+    Words,
+    /// Texts of the site data (`abstracts.jsonl`)
+    Corpus,
+    /// Titles and contents of the `sections` collection
+    Sections,
+}
+
+impl VocabSource {
+    fn parse(value: &str) -> anyhow::Result<Self> {
+        match value.trim() {
+            "words" => Ok(Self::Words),
+            "corpus" => Ok(Self::Corpus),
+            "sections" => Ok(Self::Sections),
+            other => anyhow::bail!("Unknown prefix source `{other}`"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VocabConfig {
+    /// Sources of the vocabulary (`PREFIX_SOURCE`, comma separated)
+    pub sources: Vec<VocabSource>,
+    /// Shortest prefix in characters (`PREFIX_MIN_LEN`)
+    pub min_len: usize,
+    /// Longest prefix in characters (`PREFIX_MAX_LEN`)
+    pub max_len: usize,
+    /// Minimal summed frequency of the words starting with a prefix (`PREFIX_MIN_FREQ`)
+    pub min_freq: usize,
+    /// Words that never contribute prefixes (`PREFIX_STOP_WORDS`, path to a word list)
+    pub stop_words: HashSet<String>,
+}
+
+impl VocabConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let sources = std::env::var("PREFIX_SOURCE")
+            .unwrap_or_else(|_| "words".to_string())
+            .split(',')
+            .map(VocabSource::parse)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let stop_words = match std::env::var("PREFIX_STOP_WORDS") {
+            Ok(path) => std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read stop words from {path}"))?
+                .lines()
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+            Err(_) => HashSet::new(),
+        };
+        let min_len = env_or("PREFIX_MIN_LEN", 1_usize).max(1);
+        let max_len = env_or("PREFIX_MAX_LEN", 5_usize).max(min_len);
+        if max_len > PREFIX_ID_MAX_BYTES {
+            anyhow::bail!(
+                "PREFIX_MAX_LEN {max_len} exceeds the {PREFIX_ID_MAX_BYTES} bytes of a prefix id"
+            );
+        }
+        Ok(Self {
+            sources,
+            min_len,
+            max_len,
+            min_freq: env_or("PREFIX_MIN_FREQ", 1),
+            stop_words,
+        })
+    }
+}
+
+pub fn n_chars(word: &str, n: usize) -> &str {
+    if word.len() <= n {
+        word
+    } else {
+        &word[..word.char_indices().nth(n).map_or(word.len(), |(i, _)| i)]
+    }
+}
+
+/// Split `text` into lowercase alphanumeric words.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Count the words of `text` into `counts`.
+pub fn count_words(text: &str, counts: &mut HashMap<String, usize>) {
+    for word in tokenize(text) {
+        *counts.entry(word).or_default() += 1;
+    }
+}
+
+/// Derive the prefixes of the counted words, each with the summed frequency of its words.
+///
+/// Stop words are skipped, and prefixes below `config.min_freq` are dropped. A prefix is
+/// only emitted with exactly `n` characters, so words shorter than `config.min_len` don't
+/// contribute at all. Prefixes of multibyte characters longer than `PREFIX_ID_MAX_BYTES`
+/// bytes are left out, as they have no point id.
+pub fn build_prefixes(
+    word_counts: &HashMap<String, usize>,
+    config: &VocabConfig,
+) -> BTreeMap<String, usize> {
+    let mut prefixes: BTreeMap<String, usize> = BTreeMap::new();
+    for (word, count) in word_counts {
+        if config.stop_words.contains(word) {
+            continue;
+        }
+        let word_len = word.chars().count();
+        for n in config.min_len..=config.max_len.min(word_len) {
+            let prefix = n_chars(word, n);
+            if prefix_to_num(prefix).is_none() {
+                break;
+            }
+            *prefixes.entry(prefix.to_string()).or_default() += count;
+        }
+    }
+    prefixes.retain(|_, freq| *freq >= config.min_freq);
+    prefixes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(min_len: usize, max_len: usize, min_freq: usize) -> VocabConfig {
+        VocabConfig {
+            sources: vec![VocabSource::Corpus],
+            min_len,
+            max_len,
+            min_freq,
+            stop_words: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn tokenize_lowercases_and_splits() {
+        let words: Vec<_> = tokenize("Qdrant's HNSW-index, v1.2!").collect();
+        assert_eq!(words, vec!["qdrant", "s", "hnsw", "index", "v1", "2"]);
+    }
+
+    #[test]
+    fn n_chars_counts_characters() {
+        assert_eq!(n_chars("qdrant", 3), "qdr");
+        assert_eq!(n_chars("qd", 3), "qd");
+        assert_eq!(n_chars("größe", 3), "grö");
+    }
+
+    #[test]
+    fn prefixes_are_weighted_by_frequency() {
+        let mut counts = HashMap::new();
+        count_words("hnsw hnsw hybrid", &mut counts);
+        let prefixes = build_prefixes(&counts, &config(1, 3, 1));
+        assert_eq!(prefixes["h"], 3);
+        assert_eq!(prefixes["hn"], 2);
+        assert_eq!(prefixes["hy"], 1);
+        assert!(!prefixes.contains_key("hnsw"));
+    }
+
+    #[test]
+    fn prefixes_respect_min_len_and_min_freq() {
+        let mut counts = HashMap::new();
+        count_words("qdrant qdrant quantization a", &mut counts);
+        let prefixes = build_prefixes(&counts, &config(2, 4, 2));
+        assert_eq!(
+            prefixes.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("qd".to_string(), 2),
+                ("qdr".to_string(), 2),
+                ("qdra".to_string(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn stop_words_are_skipped() {
+        let mut counts = HashMap::new();
+        count_words("the theory", &mut counts);
+        let mut config = config(3, 3, 1);
+        config.stop_words.insert("the".to_string());
+        let prefixes = build_prefixes(&counts, &config);
+        assert_eq!(prefixes["the"], 1);
+    }
+
+    #[test]
+    fn prefixes_fit_into_an_id() {
+        let mut counts = HashMap::new();
+        count_words("поиск", &mut counts);
+        let prefixes = build_prefixes(&counts, &config(1, 5, 1));
+        assert_eq!(prefixes.keys().last().map(String::as_str), Some("поис"));
+        assert!(!prefixes.contains_key("поиск"));
+    }
+}
@@ -0,0 +1,271 @@
+//! How the prefix recommendation and the embedding search of a short query are combined.
+//!
+//! Both are started for queries of typed prefixes, see `prefix_query`. Returning whichever
+//! finishes first with results is the fastest, but the same query can get different results
+//! from run to run, so the other policies trade some latency for deterministic results.
+
+use std::future::Future;
+use std::time::Duration;
+
+use futures::future::Either;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+
+use crate::common::env_or;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RacePolicy {
+    /// The first non-empty result wins
+    FirstWins,
+    /// The embedding search wins if it has results within the deadline, the prefix
+    /// recommendation after it
+    PreferEmbedding { deadline: Duration },
+    /// Both results are awaited and interleaved, starting with the embedding search
+    MergeBoth,
+    /// Only queries of up to `max_chars` characters are recommended from prefixes, and only
+    /// if that has results
+    PrefixOnlyShort { max_chars: usize },
+}
+
+impl RacePolicy {
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("SEARCH_RACE_POLICY").as_deref() {
+            Err(_) | Ok("first-wins") => Ok(Self::FirstWins),
+            Ok("prefer-embedding") => Ok(Self::PreferEmbedding {
+                deadline: Duration::from_millis(env_or("SEARCH_RACE_DEADLINE_MS", 50)),
+            }),
+            Ok("merge-both") => Ok(Self::MergeBoth),
+            Ok("prefix-only-short") => Ok(Self::PrefixOnlyShort {
+                max_chars: env_or("SEARCH_PREFIX_ONLY_MAX_CHARS", 2),
+            }),
+            Ok(other) => anyhow::bail!("Unknown search race policy `{other}`"),
+        }
+    }
+}
+
+/// Where the returned results come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaceOutcome {
+    Prefix,
+    Embedding,
+    Merged,
+    Empty,
+}
+
+fn outcome<T>(hits: Vec<T>, source: RaceOutcome) -> (Vec<T>, RaceOutcome) {
+    if hits.is_empty() {
+        (hits, RaceOutcome::Empty)
+    } else {
+        (hits, source)
+    }
+}
+
+/// Alternate the hits of `first` and `second`, skipping hits with a key seen before.
+fn interleave<T>(
+    first: Vec<T>,
+    second: Vec<T>,
+    key: impl Fn(&T) -> String,
+    limit: usize,
+) -> Vec<T> {
+    let mut seen = std::collections::HashSet::new();
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    let mut res = vec![];
+    loop {
+        let (a, b) = (first.next(), second.next());
+        if a.is_none() && b.is_none() {
+            break;
+        }
+        for hit in a.into_iter().chain(b) {
+            if res.len() < limit && seen.insert(key(&hit)) {
+                res.push(hit);
+            }
+        }
+    }
+    res
+}
+
+/// Combine the `prefix` recommendation, if the query is a prefix query, and the `embedding`
+/// search of `query` by `policy`.
+///
+/// `key` identifies a hit for merging, and at most `limit` hits are merged.
+pub async fn race<T, E>(
+    policy: RacePolicy,
+    query: &str,
+    prefix: Option<impl Future<Output = Result<Vec<T>, E>>>,
+    embedding: impl Future<Output = Result<Vec<T>, E>>,
+    key: impl Fn(&T) -> String,
+    limit: usize,
+) -> Result<(Vec<T>, RaceOutcome), E> {
+    let Some(prefix) = prefix else {
+        return Ok(outcome(embedding.await?, RaceOutcome::Embedding));
+    };
+    match policy {
+        RacePolicy::FirstWins => {
+            let mut pending = FuturesUnordered::new();
+            pending.push(Either::Left(prefix.map(|r| (RaceOutcome::Prefix, r))));
+            pending.push(Either::Right(
+                embedding.map(|r| (RaceOutcome::Embedding, r)),
+            ));
+            while let Some((source, result)) = pending.next().await {
+                let hits = result?;
+                if !hits.is_empty() {
+                    return Ok((hits, source));
+                }
+            }
+            Ok((vec![], RaceOutcome::Empty))
+        }
+        RacePolicy::PreferEmbedding { deadline } => {
+            let deadline = tokio::time::sleep(deadline);
+            tokio::pin!(prefix, embedding, deadline);
+            let mut prefix_hits: Option<Vec<T>> = None;
+            loop {
+                tokio::select! {
+                    biased;
+                    result = &mut embedding => {
+                        let hits = result?;
+                        if !hits.is_empty() {
+                            return Ok((hits, RaceOutcome::Embedding));
+                        }
+                        let hits = match prefix_hits {
+                            Some(hits) => hits,
+                            None => prefix.await?,
+                        };
+                        return Ok(outcome(hits, RaceOutcome::Prefix));
+                    }
+                    result = &mut prefix, if prefix_hits.is_none() => {
+                        prefix_hits = Some(result?);
+                    }
+                    // a completed sleep stays ready, so this fires once the prefix has results
+                    _ = &mut deadline, if prefix_hits.as_ref().is_some_and(|h| !h.is_empty()) => {
+                        return Ok((prefix_hits.unwrap_or_default(), RaceOutcome::Prefix));
+                    }
+                }
+            }
+        }
+        RacePolicy::MergeBoth => {
+            let (prefix, embedding) = futures::join!(prefix, embedding);
+            let hits = interleave(embedding?, prefix?, key, limit);
+            Ok(outcome(hits, RaceOutcome::Merged))
+        }
+        RacePolicy::PrefixOnlyShort { max_chars } => {
+            if query.trim().chars().count() <= max_chars {
+                let hits = prefix.await?;
+                if !hits.is_empty() {
+                    return Ok((hits, RaceOutcome::Prefix));
+                }
+            }
+            Ok(outcome(embedding.await?, RaceOutcome::Embedding))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A search answering `hits` after `millis`.
+    async fn search(millis: u64, hits: &[&str]) -> Result<Vec<String>, String> {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        Ok(hits.iter().map(|h| h.to_string()).collect())
+    }
+
+    async fn run(
+        policy: RacePolicy,
+        query: &str,
+        prefix: (u64, &[&str]),
+        embedding: (u64, &[&str]),
+    ) -> (Vec<String>, RaceOutcome) {
+        race(
+            policy,
+            query,
+            Some(search(prefix.0, prefix.1)),
+            search(embedding.0, embedding.1),
+            |hit| hit.clone(),
+            5,
+        )
+        .await
+        .unwrap()
+    }
+
+    const DEADLINE: RacePolicy = RacePolicy::PreferEmbedding {
+        deadline: Duration::from_millis(50),
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn first_result_wins() {
+        let policy = RacePolicy::FirstWins;
+        let fast_prefix = run(policy, "hn", (10, &["p"]), (30, &["e"])).await;
+        assert_eq!(fast_prefix, (vec!["p".to_string()], RaceOutcome::Prefix));
+        let slow_prefix = run(policy, "hn", (40, &["p"]), (30, &["e"])).await;
+        assert_eq!(slow_prefix, (vec!["e".to_string()], RaceOutcome::Embedding));
+        // empty results don't win
+        let empty_prefix = run(policy, "hn", (10, &[]), (30, &["e"])).await;
+        assert_eq!(empty_prefix.1, RaceOutcome::Embedding);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn embedding_is_preferred_until_the_deadline() {
+        let in_time = run(DEADLINE, "hn", (10, &["p"]), (40, &["e"])).await;
+        assert_eq!(in_time, (vec!["e".to_string()], RaceOutcome::Embedding));
+        let late = run(DEADLINE, "hn", (10, &["p"]), (80, &["e"])).await;
+        assert_eq!(late, (vec!["p".to_string()], RaceOutcome::Prefix));
+        // the prefix is still awaited after the deadline
+        let both_late = run(DEADLINE, "hn", (60, &["p"]), (80, &["e"])).await;
+        assert_eq!(both_late.1, RaceOutcome::Prefix);
+        let empty_prefix = run(DEADLINE, "hn", (10, &[]), (80, &["e"])).await;
+        assert_eq!(empty_prefix.1, RaceOutcome::Embedding);
+        let empty_embedding = run(DEADLINE, "hn", (30, &["p"]), (10, &[])).await;
+        assert_eq!(empty_embedding.1, RaceOutcome::Prefix);
+        let nothing = run(DEADLINE, "hn", (30, &[]), (10, &[])).await;
+        assert_eq!(nothing.1, RaceOutcome::Empty);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn results_are_merged() {
+        let merged = run(
+            RacePolicy::MergeBoth,
+            "hn",
+            (10, &["a", "b"]),
+            (30, &["c", "a", "d"]),
+        )
+        .await;
+        assert_eq!(merged.0, vec!["c", "a", "b", "d"]);
+        assert_eq!(merged.1, RaceOutcome::Merged);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn only_short_queries_use_prefixes() {
+        let policy = RacePolicy::PrefixOnlyShort { max_chars: 2 };
+        let short = run(policy, "hn", (30, &["p"]), (10, &["e"])).await;
+        assert_eq!(short.1, RaceOutcome::Prefix);
+        let longer = run(policy, "hns", (10, &["p"]), (30, &["e"])).await;
+        assert_eq!(longer.1, RaceOutcome::Embedding);
+        let no_prefix = run(policy, "hn", (10, &[]), (30, &["e"])).await;
+        assert_eq!(no_prefix.1, RaceOutcome::Embedding);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_same_query_gets_the_same_results() {
+        for policy in [DEADLINE, RacePolicy::MergeBoth] {
+            let fast_prefix = run(policy, "hn", (5, &["p"]), (20, &["e"])).await;
+            let fast_embedding = run(policy, "hn", (20, &["p"]), (5, &["e"])).await;
+            assert_eq!(fast_prefix, fast_embedding, "{policy:?}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queries_without_prefix_only_embed() {
+        let (hits, outcome) = race(
+            RacePolicy::FirstWins,
+            "hnsw index",
+            None::<std::future::Ready<Result<Vec<String>, String>>>,
+            search(10, &["e"]),
+            |hit| hit.clone(),
+            5,
+        )
+        .await
+        .unwrap();
+        assert_eq!((hits.len(), outcome), (1, RaceOutcome::Embedding));
+    }
+}
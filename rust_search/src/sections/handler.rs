@@ -1,35 +1,55 @@
-use actix_web::web::{Data, Query};
+use std::collections::HashMap;
+
+use actix_web::web::{self, Data, Query};
+use actix_web::http::header::{ACCEPT, VARY};
 use actix_web::{get, HttpRequest, HttpResponse};
+use anyhow::Context;
+use qdrant_client::qdrant::point_id::PointIdOptions;
 use qdrant_client::qdrant::r#match::MatchValue;
+use ort::Session;
 use qdrant_client::qdrant::{
-    Condition, Document, FacetCountsBuilder, Filter, QueryPointsBuilder, ScoredPoint, VectorInput,
+    Condition, CreateFieldIndexCollectionBuilder, FieldType, Filter, PayloadSchemaType, PointId,
+    QueryPointsBuilder, Range, RetrievedPoint, ScoredPoint, PayloadIncludeSelector,
+    ScrollPointsBuilder, Value,
 };
 use qdrant_client::Qdrant;
+use rust_tokenizers::tokenizer::BertTokenizer;
 use serde::Deserialize;
 
-use super::models::{Section, SectionSearchResult, slugify_heading};
+use crate::cache::HttpCache;
+use crate::common::{env_or, SECTION_COLLECTION_NAME};
+use crate::format::{resolve_format, ResponseFormat};
+
+use super::models::{
+    PageCursors, Section, SectionSearchResult, slugify_heading, CHARS_PER_TOKEN,
+};
+use super::navigation::{Heading, Navigation, HEADING_FIELDS};
+use super::ranking::{order_by_scores, SectionRanker};
+use super::toc::Toc;
 
-const SECTION_COLLECTION_NAME: &str = "sections";
+const MD_FORMATS: &[ResponseFormat] = &[
+    ResponseFormat::Json,
+    ResponseFormat::Markdown,
+    ResponseFormat::Html,
+];
 
 fn sections_exact_limit() -> u64 {
-    std::env::var("SECTIONS_EXACT_LIMIT")
-        .ok()
-        .and_then(|v| v.parse().ok())
-        .unwrap_or(100)
+    env_or("SECTIONS_EXACT_LIMIT", 100)
 }
 
 fn sections_search_limit() -> u64 {
-    std::env::var("SECTIONS_SEARCH_LIMIT")
-        .ok()
-        .and_then(|v| v.parse().ok())
-        .unwrap_or(10)
+    env_or("SECTIONS_SEARCH_LIMIT", 10)
 }
 
-fn parse_sections(points: Vec<ScoredPoint>) -> Vec<Section> {
-    points
-        .into_iter()
-        .filter_map(|p| Section::from_payload(p.payload))
-        .collect()
+/// Points per request when scrolling through all matching sections
+const SCROLL_LIMIT: u32 = 256;
+
+/// The items of all points whose payload `parse` accepts, e.g. `Section::from_payload`.
+pub fn parse_points<T>(
+    points: Vec<ScoredPoint>,
+    parse: impl Fn(HashMap<String, Value>) -> Option<T>,
+) -> Vec<T> {
+    points.into_iter().filter_map(|p| parse(p.payload)).collect()
 }
 
 fn build_conditions(path: &str, query: Option<&str>, section: Option<&str>) -> Vec<Condition> {
@@ -55,14 +75,16 @@ fn build_conditions(path: &str, query: Option<&str>, section: Option<&str>) -> V
     conditions
 }
 
-async fn query_by_filter(
+/// Points of `collection` matching all `conditions`, in no particular order.
+pub async fn query_by_filter(
     client: &Qdrant,
+    collection: &str,
     conditions: Vec<Condition>,
     limit: u64,
 ) -> anyhow::Result<Vec<ScoredPoint>> {
     let result = client
         .query(
-            QueryPointsBuilder::new(SECTION_COLLECTION_NAME)
+            QueryPointsBuilder::new(collection)
                 .filter(Filter::must(conditions))
                 .limit(limit)
                 .with_payload(true),
@@ -71,29 +93,53 @@ async fn query_by_filter(
     Ok(result.result)
 }
 
-const NEURAL_ENCODER: &str = "sentence-transformers/all-MiniLM-L6-v2";
+/// What a vector search of sections needs besides the client.
+struct Ranking {
+    ranker: Data<SectionRanker>,
+    /// Tokenizer and model of the service, for embedding queries locally
+    context: Data<(BertTokenizer, Session, Qdrant)>,
+}
 
 async fn query_by_document(
     client: &Qdrant,
     query: &str,
     conditions: Vec<Condition>,
-) -> anyhow::Result<Vec<ScoredPoint>> {
-    let result = client
-        .query(
-            QueryPointsBuilder::new(SECTION_COLLECTION_NAME)
-                .query(VectorInput::from(Document::new(query, NEURAL_ENCODER)))
-                .filter(Filter::must(conditions))
-                .limit(sections_search_limit())
-                .with_payload(true),
-        )
-        .await?;
-    Ok(result.result)
+    ranking: &Ranking,
+) -> anyhow::Result<Vec<Section>> {
+    let limit = sections_search_limit();
+    let ranker = ranking.ranker.clone();
+    let context = ranking.context.clone();
+    let text = query.to_string();
+    // the models run on the blocking pool, not on the workers serving requests
+    let dense = web::block(move || {
+        let (tokenizer, session, _) = context.get_ref();
+        ranker.dense_input(&text, (tokenizer, session))
+    })
+    .await??;
+    let ranker = ranking.ranker.clone();
+    let request = ranker.build_query(query, dense, conditions, ranker.candidates(limit));
+    let sections = parse_points(client.query(request).await?.result, Section::from_payload);
+    if ranker.reranker.is_none() {
+        return Ok(sections);
+    }
+
+    let text = query.to_string();
+    web::block(move || {
+        let Some(reranker) = &ranker.reranker else {
+            return Ok(sections);
+        };
+        let texts: Vec<&str> = sections.iter().map(|s| s.content.as_str()).collect();
+        let scores = reranker.scores(&text, &texts)?;
+        Ok(order_by_scores(sections, &scores, limit as usize))
+    })
+    .await?
 }
 
 async fn search_by_query(
     client: &Qdrant,
     query: &str,
     conditions: Vec<Condition>,
+    ranking: &Ranking,
 ) -> anyhow::Result<SectionSearchResult> {
     // Try exact slug match first
     let slug = slugify_heading(query);
@@ -103,53 +149,243 @@ async fn search_by_query(
         MatchValue::Keyword(slug),
     ));
 
-    let points = query_by_filter(client, exact_conditions, sections_exact_limit()).await?;
+    let points = query_by_filter(
+        client,
+        SECTION_COLLECTION_NAME,
+        exact_conditions,
+        sections_exact_limit(),
+    )
+    .await?;
     if !points.is_empty() {
         return Ok(SectionSearchResult {
-            sections: parse_sections(points),
+            sections: parse_points(points, Section::from_payload),
             sublinks: None,
+            omitted: vec![],
+            next: PageCursors::default(),
+            navigation: None,
         });
     }
 
-    // Fallback to server-side vector search
-    let points = query_by_document(client, query, conditions).await?;
+    // Fallback to vector search
+    let sections = query_by_document(client, query, conditions, ranking).await?;
     Ok(SectionSearchResult {
-        sections: parse_sections(points),
+        sections,
         sublinks: None,
+        omitted: vec![],
+        next: PageCursors::default(),
+        navigation: None,
     })
 }
 
-async fn fetch_sublinks(client: &Qdrant, path: &str) -> anyhow::Result<Vec<String>> {
-    let facet_result = client
-        .facet(
-            FacetCountsBuilder::new(SECTION_COLLECTION_NAME, "page")
-                .filter(Filter {
-                    must: vec![Condition::matches(
-                        "parent_pages",
-                        MatchValue::Keyword(path.to_string()),
-                    )],
-                    must_not: vec![Condition::matches(
-                        "page",
-                        MatchValue::Keyword(path.to_string()),
-                    )],
-                    ..Default::default()
-                })
-                .limit(sections_exact_limit()),
-        )
-        .await?;
+/// One batch of a scroll through the sections matching `filter`, starting at `offset`.
+pub(super) fn scroll_request(
+    filter: &Filter,
+    fields: Option<&[&str]>,
+    offset: Option<PointId>,
+) -> ScrollPointsBuilder {
+    let mut request = ScrollPointsBuilder::new(SECTION_COLLECTION_NAME)
+        .filter(filter.clone())
+        .limit(SCROLL_LIMIT);
+    request = match fields {
+        Some(fields) => request.with_payload(PayloadIncludeSelector {
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+        }),
+        None => request.with_payload(true),
+    };
+    if let Some(offset) = offset {
+        request = request.offset::<PointId>(offset);
+    }
+    request
+}
+
+/// Scroll through all sections matching `filter`, with all or only the given payload `fields`.
+pub(super) async fn scroll_all(
+    client: &Qdrant,
+    filter: Filter,
+    fields: Option<&[&str]>,
+) -> anyhow::Result<Vec<RetrievedPoint>> {
+    let mut points = vec![];
+    let mut offset = None;
+    loop {
+        let request = scroll_request(&filter, fields, offset.take());
+        let response = client.scroll(request).await?;
+        points.extend(response.result);
+        match response.next_page_offset {
+            Some(next) => offset = Some(next),
+            None => break,
+        }
+    }
+    Ok(points)
+}
 
-    let mut links: Vec<String> = facet_result
-        .hits
+/// A page of sublinks starting at the scroll offset `offset`, and the offset of the next one.
+///
+/// Every page has a section at line 0, so the subpages are the pages of those sections. They
+/// are sorted within a page of sublinks, the pages follow the order of the scroll.
+async fn fetch_sublinks(
+    client: &Qdrant,
+    path: &str,
+    offset: Option<PointId>,
+) -> anyhow::Result<(Vec<String>, Option<String>)> {
+    let filter = Filter {
+        must: vec![
+            Condition::matches("parent_pages", MatchValue::Keyword(path.to_string())),
+            Condition::matches("line", 0),
+        ],
+        must_not: vec![Condition::matches(
+            "page",
+            MatchValue::Keyword(path.to_string()),
+        )],
+        ..Default::default()
+    };
+    let request = scroll_request(&filter, Some(&["page"]), offset)
+        .limit(sections_exact_limit() as u32);
+    let response = client.scroll(request).await?;
+    let mut links: Vec<String> = response
+        .result
         .into_iter()
-        .filter_map(|hit| {
-            hit.value.and_then(|v| match v.variant? {
-                qdrant_client::qdrant::facet_value::Variant::StringValue(s) => Some(s),
-                _ => None,
-            })
-        })
+        .filter_map(|p| p.payload.get("page")?.as_str().cloned())
         .collect();
     links.sort();
-    Ok(links)
+    Ok((links, response.next_page_offset.and_then(point_id_to_cursor)))
+}
+
+fn point_id_to_cursor(id: PointId) -> Option<String> {
+    match id.point_id_options? {
+        PointIdOptions::Num(num) => Some(num.to_string()),
+        PointIdOptions::Uuid(uuid) => Some(uuid),
+    }
+}
+
+/// The point id of a cursor, a number or a UUID.
+fn cursor_to_point_id(cursor: &str) -> Option<PointId> {
+    if let Ok(num) = cursor.parse::<u64>() {
+        return Some(PointId::from(num));
+    }
+    let is_uuid = cursor.len() == 36 && cursor.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+    is_uuid.then(|| PointId::from(cursor))
+}
+
+/// Keep the first `limit` of `items`, and return the cursor of the last kept one if more follow.
+fn truncate_page<T>(
+    items: &mut Vec<T>,
+    limit: usize,
+    cursor: impl Fn(&T) -> String,
+) -> Option<String> {
+    if items.len() > limit {
+        items.truncate(limit);
+        items.last().map(cursor)
+    } else {
+        None
+    }
+}
+
+/// Make sure that `line` has the integer index that paging sections in line order needs.
+///
+/// Collections written by `site_search/sections.py` have it, older ones get it created.
+pub async fn ensure_line_index(client: &Qdrant) -> anyhow::Result<()> {
+    if !client.collection_exists(SECTION_COLLECTION_NAME).await? {
+        log::warn!("No {} collection, /md/ pages are not available", SECTION_COLLECTION_NAME);
+        return Ok(());
+    }
+    let schema = client
+        .collection_info(SECTION_COLLECTION_NAME)
+        .await?
+        .result
+        .map(|info| info.payload_schema)
+        .unwrap_or_default();
+    match schema.get("line").map(|info| info.data_type) {
+        Some(data_type) if data_type == PayloadSchemaType::Integer as i32 => Ok(()),
+        Some(_) => anyhow::bail!(
+            "The `line` index of {} is not an integer index, as paging sections needs",
+            SECTION_COLLECTION_NAME
+        ),
+        None => {
+            log::info!("Creating the integer index of `line` in {}", SECTION_COLLECTION_NAME);
+            client
+                .create_field_index(
+                    CreateFieldIndexCollectionBuilder::new(
+                        SECTION_COLLECTION_NAME,
+                        "line",
+                        FieldType::Integer,
+                    )
+                    .wait(true),
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to create the integer index of `line` in {}, \
+                         needed to page sections in line order",
+                        SECTION_COLLECTION_NAME
+                    )
+                })?;
+            Ok(())
+        }
+    }
+}
+
+/// A page of sections in line order after the line `after`, and the cursor of the next one.
+async fn scroll_sections(
+    client: &Qdrant,
+    mut conditions: Vec<Condition>,
+    after: Option<i64>,
+) -> anyhow::Result<(Vec<Section>, Option<String>)> {
+    if let Some(after) = after {
+        conditions.push(Condition::range(
+            "line",
+            Range {
+                gt: Some(after as f64),
+                ..Default::default()
+            },
+        ));
+    }
+    let limit = sections_exact_limit() as u32;
+    let request = ScrollPointsBuilder::new(SECTION_COLLECTION_NAME)
+        .filter(Filter::must(conditions))
+        .order_by("line")
+        .limit(limit + 1)
+        .with_payload(true);
+    let mut sections: Vec<Section> = client
+        .scroll(request)
+        .await?
+        .result
+        .into_iter()
+        .filter_map(|p| Section::from_payload(p.payload))
+        .collect();
+    let next = truncate_page(&mut sections, limit as usize, |s| s.line.to_string());
+    Ok((sections, next))
+}
+
+/// Headings of all sections of a page, without their content.
+async fn fetch_outline(client: &Qdrant, path: &str) -> anyhow::Result<Vec<Heading>> {
+    let filter = Filter::must([Condition::matches(
+        "page",
+        MatchValue::Keyword(path.to_string()),
+    )]);
+    Ok(scroll_all(client, filter, Some(HEADING_FIELDS))
+        .await?
+        .into_iter()
+        .filter_map(|p| Heading::from_payload(p.payload))
+        .collect())
+}
+
+/// Sections of `page` with only the heading fields of `outline`, in or below `section`.
+fn outline_sections(page: &str, outline: &[Heading], section: Option<&str>) -> Vec<Section> {
+    outline
+        .iter()
+        .filter(|h| section.is_none_or(|slug| h.parent_sections.iter().any(|s| s == slug)))
+        .map(|h| Section {
+            title: h.title.clone(),
+            slug: h.slug.clone(),
+            content: String::new(),
+            url: page.to_string(),
+            page: page.to_string(),
+            parent_sections: h.parent_sections.clone(),
+            parent_pages: vec![],
+            level: h.level,
+            line: h.line,
+        })
+        .collect()
 }
 
 async fn browse_sections(
@@ -157,14 +393,27 @@ async fn browse_sections(
     path: &str,
     section: Option<&str>,
     conditions: Vec<Condition>,
+    cursors: &Cursors,
+    toc: bool,
 ) -> anyhow::Result<Option<SectionSearchResult>> {
-    let points = query_by_filter(client, conditions, sections_exact_limit()).await?;
-    let sections = parse_sections(points);
+    let outline = if toc || section.is_some() {
+        fetch_outline(client, path).await?
+    } else {
+        vec![]
+    };
+    // a table of contents needs all headings, but none of the contents
+    let (sections, next_sections) = if toc {
+        (outline_sections(path, &outline, section), None)
+    } else {
+        scroll_sections(client, conditions, cursors.after_line).await?
+    };
 
-    let sublinks = if section.is_none() {
-        Some(fetch_sublinks(client, path).await?)
+    let (sublinks, next_sublinks) = if section.is_none() {
+        let (sublinks, next) =
+            fetch_sublinks(client, path, cursors.sublinks_offset.clone()).await?;
+        (Some(sublinks), next)
     } else {
-        None
+        (None, None)
     };
 
     let is_empty = sections.is_empty()
@@ -174,7 +423,16 @@ async fn browse_sections(
         return Ok(None);
     }
 
-    Ok(Some(SectionSearchResult { sections, sublinks }))
+    Ok(Some(SectionSearchResult {
+        sections,
+        sublinks,
+        omitted: vec![],
+        next: PageCursors {
+            sections: next_sections,
+            sublinks: next_sublinks,
+        },
+        navigation: Some(Navigation::new(path, section, &outline)),
+    }))
 }
 
 async fn search_sections(
@@ -182,13 +440,16 @@ async fn search_sections(
     query: Option<&str>,
     path: &str,
     section: Option<&str>,
+    cursors: &Cursors,
+    ranking: &Ranking,
+    toc: bool,
 ) -> anyhow::Result<Option<SectionSearchResult>> {
     let clean_path = path.trim_matches('/');
     let conditions = build_conditions(clean_path, query, section);
 
     match query {
-        Some(q) => Ok(Some(search_by_query(client, q, conditions).await?)),
-        None => browse_sections(client, clean_path, section, conditions).await,
+        Some(q) => Ok(Some(search_by_query(client, q, conditions, ranking).await?)),
+        None => browse_sections(client, clean_path, section, conditions, cursors, toc).await,
     }
 }
 
@@ -196,6 +457,45 @@ async fn search_sections(
 struct MdSearch {
     q: Option<String>,
     s: Option<String>,
+    format: Option<String>,
+    /// Approximate size limit of the section contents in LLM tokens
+    max_tokens: Option<usize>,
+    /// Size limit of the section contents in characters, takes precedence over `max_tokens`
+    max_chars: Option<usize>,
+    /// Render only the outline of the headings and subpages (`?toc=1`)
+    toc: Option<String>,
+    /// Line of the last section of the previous page, from a "next page" link
+    offset: Option<String>,
+    /// Scroll offset of the sublinks, from a "next page" link
+    sublinks_offset: Option<String>,
+}
+
+impl MdSearch {
+    fn toc(&self) -> bool {
+        matches!(self.toc.as_deref(), Some("1" | "true"))
+    }
+
+    /// The positions to continue browsing at, or the malformed `offset` or `sublinks_offset`.
+    fn cursors(&self) -> Result<Cursors, &str> {
+        let after_line = match self.offset.as_deref() {
+            Some(offset) => Some(offset.parse().map_err(|_| offset)?),
+            None => None,
+        };
+        let sublinks_offset = match self.sublinks_offset.as_deref() {
+            Some(offset) => Some(cursor_to_point_id(offset).ok_or(offset)?),
+            None => None,
+        };
+        Ok(Cursors {
+            after_line,
+            sublinks_offset,
+        })
+    }
+}
+
+/// Parsed `offset` and `sublinks_offset` of a request.
+struct Cursors {
+    after_line: Option<i64>,
+    sublinks_offset: Option<PointId>,
 }
 
 #[get("/md/{path:.*}")]
@@ -204,31 +504,86 @@ pub async fn md_handler(
     req: HttpRequest,
     query: Query<MdSearch>,
     qdrant: Data<Qdrant>,
+    ranker: Data<SectionRanker>,
+    context: Data<(BertTokenizer, Session, Qdrant)>,
+    cache: Data<HttpCache>,
 ) -> HttpResponse {
+    let cache_control = &cache.config.md_cache_control;
+    let validators = cache.validators(SECTION_COLLECTION_NAME, &req).await;
+    if let Some(validators) = validators.as_ref().filter(|v| v.is_fresh(&req)) {
+        return validators.not_modified(cache_control);
+    }
+
+    let cursors = match query.cursors() {
+        Ok(cursors) => cursors,
+        Err(offset) => {
+            return HttpResponse::BadRequest().body(format!("Invalid offset `{offset}`"));
+        }
+    };
     let path_str = path.into_inner();
     let qdrant = qdrant.get_ref();
+    let ranking = Ranking { ranker, context };
 
     let result = search_sections(
         qdrant,
         query.q.as_deref(),
         &path_str,
         query.s.as_deref(),
+        &cursors,
+        &ranking,
+        query.toc(),
     )
     .await;
 
     match result {
-        Ok(Some(section_result)) => {
-            log::info!("sections={}", section_result.sections.len());
-
+        Ok(Some(mut section_result)) => {
             let conn = req.connection_info();
             let base_url = format!("{}://{}", conn.scheme(), conn.host());
             let request_path = req.uri().path();
             let request_query = req.uri().query();
 
-            let markdown = section_result.to_markdown(request_path, request_query, &base_url);
-            HttpResponse::Ok()
-                .content_type("text/markdown; charset=utf-8")
-                .body(markdown)
+            let budget = query
+                .max_chars
+                .or(query.max_tokens.map(|tokens| tokens.saturating_mul(CHARS_PER_TOKEN)));
+            if let Some(max_chars) = budget.filter(|_| !query.toc()) {
+                section_result.apply_budget(
+                    max_chars,
+                    query.s.as_deref(),
+                    request_path,
+                    request_query,
+                    &base_url,
+                );
+            }
+            log::info!(
+                "sections={} omitted={}",
+                section_result.sections.len(),
+                section_result.omitted.len()
+            );
+
+            let accept = req.headers().get(ACCEPT).and_then(|v| v.to_str().ok());
+            let format = resolve_format(query.format.as_deref(), accept, MD_FORMATS);
+            let body = if query.toc() {
+                let toc = Toc::new(&section_result, request_path, request_query, &base_url);
+                match format {
+                    ResponseFormat::Json => toc.to_json(),
+                    ResponseFormat::Markdown => toc.to_markdown(),
+                    ResponseFormat::Html => toc.to_html(),
+                }
+            } else {
+                match format {
+                    ResponseFormat::Json => section_result.to_json(request_path, request_query, &base_url),
+                    ResponseFormat::Markdown => section_result.to_markdown(request_path, request_query, &base_url),
+                    ResponseFormat::Html => section_result.to_html(request_path, request_query, &base_url),
+                }
+            };
+            let mut response = HttpResponse::Ok();
+            if let Some(validators) = &validators {
+                validators.apply(&mut response, cache_control);
+            }
+            response
+                .content_type(format.content_type())
+                .insert_header((VARY, "Accept"))
+                .body(body)
         }
         Ok(None) => {
             HttpResponse::NotFound().body("Page or section not found")
@@ -239,3 +594,63 @@ pub async fn md_handler(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_end_at_limit() {
+        let mut lines = vec![0, 4, 9];
+        assert_eq!(truncate_page(&mut lines, 3, i32::to_string), None);
+        assert_eq!(truncate_page(&mut lines, 2, i32::to_string), Some("4".to_string()));
+        assert_eq!(lines, vec![0, 4]);
+    }
+
+    #[test]
+    fn offsets_are_lines() {
+        let search = |offset: &str| MdSearch {
+            q: None,
+            s: None,
+            format: None,
+            max_tokens: None,
+            max_chars: None,
+            toc: None,
+            offset: Some(offset.to_string()),
+            sublinks_offset: None,
+        };
+        assert_eq!(search("42").cursors().unwrap().after_line, Some(42));
+        assert!(search("5f0b7d0c").cursors().is_err());
+    }
+
+    #[test]
+    fn sublinks_offsets_are_point_ids() {
+        let uuid = "5f0b7d0c-8d5a-4c2e-9a6b-1f2e3d4c5b6a";
+        assert_eq!(cursor_to_point_id(uuid), Some(PointId::from(uuid)));
+        assert_eq!(cursor_to_point_id("42"), Some(PointId::from(42)));
+        assert_eq!(cursor_to_point_id("documentation/guides"), None);
+        assert_eq!(point_id_to_cursor(PointId::from(uuid)).as_deref(), Some(uuid));
+    }
+
+    #[test]
+    fn outline_of_a_section() {
+        let heading = |slug: &str, line, parent_sections: &[&str]| Heading {
+            title: slug.to_uppercase(),
+            slug: slug.to_string(),
+            level: parent_sections.len() as i64,
+            line,
+            parent_sections: parent_sections.iter().map(|s| s.to_string()).collect(),
+        };
+        let outline = vec![
+            heading("guides", 1, &["guides"]),
+            heading("install", 10, &["guides", "install"]),
+            heading("docker", 20, &["guides", "install", "docker"]),
+            heading("faq", 30, &["guides", "faq"]),
+        ];
+        assert_eq!(outline_sections("documentation/guides", &outline, None).len(), 4);
+        let sections = outline_sections("documentation/guides", &outline, Some("install"));
+        let slugs: Vec<_> = sections.iter().map(|s| s.slug.as_str()).collect();
+        assert_eq!(slugs, vec!["install", "docker"]);
+        assert_eq!(sections[1].page, "documentation/guides");
+    }
+}
@@ -1,4 +1,7 @@
-use regex::{Captures, Regex};
+use std::collections::HashSet;
+use std::ops::Range;
+
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd};
 
 /// Rewrite markdown links in `content` for the `/md/` service.
 ///
@@ -6,27 +9,138 @@ use regex::{Captures, Regex};
 /// 2. Convert `#fragment` into `?s=fragment`
 /// 3. Add `/md` prefix only for absolute paths (starting with `/`)
 /// 4. Leave relative links relative; leave external links unchanged
+///
+/// Links are found with a CommonMark parser, and only their destinations are replaced in
+/// the source, so the rest of the markdown is kept as is. Inline links and reference
+/// definitions are rewritten. Images, autolinks and anything inside code are left alone.
 pub fn rewrite_links(content: &str) -> String {
-    let re = Regex::new(r"\[([^\]]*)\]\(([^)]+)\)").unwrap();
+    let mut edits: Vec<(Range<usize>, String)> = vec![];
+    let mut parser = Parser::new_ext(content, Options::ENABLE_TABLES).into_offset_iter();
+    let events: Vec<_> = parser.by_ref().collect();
+    let ref_defs = parser.reference_definitions();
+
+    // Reference definitions used by images point to images, not pages
+    let mut image_refs = HashSet::new();
+    // Range of the inline link being parsed and the end of its text
+    let mut inline_link: Option<(Range<usize>, usize)> = None;
+
+    for (event, range) in events {
+        match event {
+            Event::Start(Tag::Link { link_type: LinkType::Inline, .. }) => {
+                inline_link = Some((range.clone(), range.start + 1));
+            }
+            Event::End(TagEnd::Link) => {
+                if let Some((link, text_end)) = inline_link.take() {
+                    // The destination follows the `](` after the link text
+                    let dest_start = content[text_end..link.end]
+                        .find("](")
+                        .map(|idx| text_end + idx + 2);
+                    if let Some(edit) = dest_start.and_then(|start| rewrite_dest(content, start)) {
+                        edits.push(edit);
+                    }
+                }
+            }
+            Event::Start(Tag::Image { link_type, id, .. }) if is_reference(link_type) => {
+                if let Some(def) = ref_defs.get(&id) {
+                    image_refs.insert(def.span.clone());
+                }
+            }
+            _ => {
+                if let Some((_, text_end)) = inline_link.as_mut() {
+                    *text_end = (*text_end).max(range.end);
+                }
+            }
+        }
+    }
+
+    for (_, def) in ref_defs.iter() {
+        if image_refs.contains(&def.span) {
+            continue;
+        }
+        // The destination follows the `]:` after the label
+        let dest_start = content[def.span.clone()]
+            .find("]:")
+            .map(|idx| def.span.start + idx + 2);
+        if let Some(edit) = dest_start.and_then(|start| rewrite_dest(content, start)) {
+            edits.push(edit);
+        }
+    }
 
-    re.replace_all(content, |caps: &Captures| {
-        let text = &caps[1];
-        let raw = caps[2].trim();
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut result = String::with_capacity(content.len());
+    let mut pos = 0;
+    for (range, replacement) in edits {
+        if range.start < pos {
+            continue;
+        }
+        result.push_str(&content[pos..range.start]);
+        result.push_str(&replacement);
+        pos = range.end;
+    }
+    result.push_str(&content[pos..]);
+    result
+}
+
+fn is_reference(link_type: LinkType) -> bool {
+    matches!(
+        link_type,
+        LinkType::Reference | LinkType::Collapsed | LinkType::Shortcut
+    )
+}
 
-        // Separate an optional markdown title: [txt](url "title")
-        let (url, title) = match raw.find(" \"") {
-            Some(idx) => (&raw[..idx], Some(&raw[idx..])),
-            None => (raw, None),
-        };
+/// Find the link destination starting at `start` (after optional whitespace) and
+/// return its range together with the rewritten destination, if it changes.
+fn rewrite_dest(content: &str, start: usize) -> Option<(Range<usize>, String)> {
+    let rest = &content[start..];
+    let offset = rest.len() - rest.trim_start().len();
+    let dest_start = start + offset;
+    let rest = &content[dest_start..];
+
+    let range = if let Some(inner) = rest.strip_prefix('<') {
+        // <destination with spaces>
+        let end = find_unescaped(inner, |c| c == '>')?;
+        dest_start + 1..dest_start + 1 + end
+    } else {
+        // destination with balanced parentheses, up to whitespace or the closing `)`
+        let mut depth = 0_usize;
+        let end = find_unescaped(rest, |c| match c {
+            '(' => {
+                depth += 1;
+                false
+            }
+            ')' if depth == 0 => true,
+            ')' => {
+                depth -= 1;
+                false
+            }
+            c => c.is_whitespace(),
+        })
+        .unwrap_or(rest.len());
+        dest_start..dest_start + end
+    };
 
-        let new_url = transform_link(url);
+    let dest = &content[range.clone()];
+    let new_dest = transform_link(dest);
+    if dest.is_empty() || new_dest == dest {
+        None
+    } else {
+        Some((range, new_dest))
+    }
+}
 
-        match title {
-            Some(t) => format!("[{}]({}{})", text, new_url, t),
-            None => format!("[{}]({})", text, new_url),
+/// Byte index of the first character matching `is_end` that is not escaped by a backslash.
+fn find_unescaped(text: &str, mut is_end: impl FnMut(char) -> bool) -> Option<usize> {
+    let mut escaped = false;
+    for (idx, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if is_end(c) {
+            return Some(idx);
         }
-    })
-    .to_string()
+    }
+    None
 }
 
 fn is_external(url: &str) -> bool {
@@ -240,6 +354,78 @@ mod tests {
         );
     }
 
+    // ── markdown constructs the regex got wrong ─────────────────────
+
+    #[test]
+    fn url_with_parentheses() {
+        assert_eq!(
+            rewrite_links("[a](/docs/foo_(bar)/index.md#baz)"),
+            "[a](/md/docs/foo_(bar)?s=baz)"
+        );
+    }
+
+    #[test]
+    fn nested_brackets_in_text() {
+        assert_eq!(
+            rewrite_links("[a [b] c](/documentation/foo)"),
+            "[a [b] c](/md/documentation/foo)"
+        );
+    }
+
+    #[test]
+    fn image_unchanged() {
+        let md = "![alt](/images/logo.png) and ![x](#frag)";
+        assert_eq!(rewrite_links(md), md);
+    }
+
+    #[test]
+    fn image_inside_link() {
+        assert_eq!(
+            rewrite_links("[![alt](/images/logo.png)](/documentation/foo)"),
+            "[![alt](/images/logo.png)](/md/documentation/foo)"
+        );
+    }
+
+    #[test]
+    fn reference_style_link() {
+        let md = "See [the guide][guide] and [faq].\n\n[guide]: /documentation/guide/index.md#intro\n[faq]: #faq \"FAQ\"\n";
+        let expected = "See [the guide][guide] and [faq].\n\n[guide]: /md/documentation/guide?s=intro\n[faq]: ?s=faq \"FAQ\"\n";
+        assert_eq!(rewrite_links(md), expected);
+    }
+
+    #[test]
+    fn reference_used_by_image_unchanged() {
+        let md = "![logo][img]\n\n[img]: /images/logo.png\n";
+        assert_eq!(rewrite_links(md), md);
+    }
+
+    #[test]
+    fn autolink_unchanged() {
+        let md = "Go to <https://qdrant.tech/documentation/> now";
+        assert_eq!(rewrite_links(md), md);
+    }
+
+    #[test]
+    fn fenced_code_unchanged() {
+        let md = "```md\n[a](/documentation/foo)\n```\n[b](/documentation/bar)";
+        let expected = "```md\n[a](/documentation/foo)\n```\n[b](/md/documentation/bar)";
+        assert_eq!(rewrite_links(md), expected);
+    }
+
+    #[test]
+    fn inline_code_unchanged() {
+        let md = "Use `[a](#sec)` to link";
+        assert_eq!(rewrite_links(md), md);
+    }
+
+    #[test]
+    fn angle_bracket_destination() {
+        assert_eq!(
+            rewrite_links("[a](</documentation/my page/index.md> \"t\")"),
+            "[a](</md/documentation/my page> \"t\")"
+        );
+    }
+
     // ── transform_link unit tests ───────────────────────────────────
 
     #[test]
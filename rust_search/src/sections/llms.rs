@@ -0,0 +1,220 @@
+use actix_web::web::{Bytes, Data};
+use actix_web::{get, HttpRequest, HttpResponse};
+use futures::{stream, Stream, StreamExt};
+use qdrant_client::qdrant::{Condition, Filter, RetrievedPoint};
+use qdrant_client::Qdrant;
+
+use super::handler::scroll_all;
+use super::links::rewrite_links;
+use super::models::Section;
+
+const DEFAULT_TITLE: &str = "Qdrant";
+const DEFAULT_SUMMARY: &str =
+    "Qdrant is a vector similarity search engine and vector database.";
+
+fn llms_title() -> String {
+    std::env::var("LLMS_TITLE").unwrap_or_else(|_| DEFAULT_TITLE.to_string())
+}
+
+fn llms_summary() -> String {
+    std::env::var("LLMS_SUMMARY").unwrap_or_else(|_| DEFAULT_SUMMARY.to_string())
+}
+
+fn base_url(req: &HttpRequest) -> String {
+    let conn = req.connection_info();
+    format!("{}://{}", conn.scheme(), conn.host())
+}
+
+fn header() -> String {
+    format!("# {}\n\n> {}\n", llms_title(), llms_summary())
+}
+
+fn field<'a>(point: &'a RetrievedPoint, name: &str) -> Option<&'a str> {
+    point
+        .payload
+        .get(name)
+        .and_then(|v| v.as_str())
+        .map(String::as_str)
+}
+
+/// Index line of a page, titled by its first section.
+fn index_line(point: &RetrievedPoint, base_url: &str) -> Option<String> {
+    let page = field(point, "page")?;
+    let title = field(point, "title")
+        .filter(|title| !title.is_empty())
+        .unwrap_or(page);
+    Some(format!("- [{}]({}/md/{})\n", title, base_url, page))
+}
+
+/// Sections of a page in document order, with links rewritten for the `/md/` service.
+fn page_text(page: &str, mut sections: Vec<Section>, base_url: &str) -> String {
+    sections.sort_by(|a, b| (&a.url, a.line).cmp(&(&b.url, b.line)));
+    let content = sections
+        .iter()
+        .map(|s| rewrite_links(&s.content))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("\n---\n\nSource: {}/md/{}\n\n{}\n", base_url, page, content)
+}
+
+async fn page_content(client: &Qdrant, page: &str, base_url: &str) -> anyhow::Result<String> {
+    let filter = Filter::must([Condition::matches("page", page.to_string())]);
+    let sections = scroll_all(client, filter, None)
+        .await?
+        .into_iter()
+        .filter_map(|p| Section::from_payload(p.payload))
+        .collect();
+    Ok(page_text(page, sections, base_url))
+}
+
+/// The first section of every page with its `page`, `title` and `url`, sorted by `url`.
+///
+/// Every page has a section at line 0. The keyword `url` can't be ordered by in Qdrant, so
+/// these few fields of all pages are sorted here, and the contents are read page by page.
+async fn first_sections(client: &Qdrant) -> anyhow::Result<Vec<RetrievedPoint>> {
+    let filter = Filter::must([Condition::matches("line", 0)]);
+    let mut points = scroll_all(client, filter, Some(&["page", "title", "url"])).await?;
+    sort_by_url(&mut points);
+    Ok(points)
+}
+
+fn sort_by_url(points: &mut [RetrievedPoint]) {
+    points.sort_by(|a, b| {
+        (field(a, "url"), field(a, "page")).cmp(&(field(b, "url"), field(b, "page")))
+    });
+}
+
+fn export_error(e: anyhow::Error) -> HttpResponse {
+    log::error!("llms.txt export error: {}", e);
+    HttpResponse::InternalServerError().body(e.to_string())
+}
+
+/// Turn a stream of rendered parts into a streamed text response.
+///
+/// Errors after the first part can't change the status anymore, so they end the stream.
+fn text_stream(
+    parts: impl Stream<Item = anyhow::Result<String>> + 'static,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    parts.map(|part| {
+        part.map(Bytes::from).map_err(|e| {
+            log::error!("llms.txt export error: {}", e);
+            actix_web::error::ErrorInternalServerError(e)
+        })
+    })
+}
+
+/// Site index following the llms.txt convention, with one link per page in `url` order.
+#[get("/llms.txt")]
+pub async fn llms_txt(req: HttpRequest, qdrant: Data<Qdrant>) -> HttpResponse {
+    let base_url = base_url(&req);
+    let points = match first_sections(qdrant.get_ref()).await {
+        Ok(points) => points,
+        Err(e) => return export_error(e),
+    };
+    let head = format!(
+        "{}\n## Optional\n\n- [Full documentation]({}/llms-full.txt)\n\n## Pages\n\n",
+        header(),
+        base_url
+    );
+    let lines = stream::iter(points)
+        .filter_map(move |point| std::future::ready(index_line(&point, &base_url).map(Ok)));
+    let body = stream::once(async { Ok(head) }).chain(lines);
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .streaming(text_stream(body))
+}
+
+/// The content of every page, concatenated page by page in `url` and line order.
+#[get("/llms-full.txt")]
+pub async fn llms_full_txt(req: HttpRequest, qdrant: Data<Qdrant>) -> HttpResponse {
+    let base_url = base_url(&req);
+    let pages: Vec<String> = match first_sections(qdrant.get_ref()).await {
+        Ok(points) => points
+            .iter()
+            .filter_map(|point| field(point, "page").map(str::to_string))
+            .collect(),
+        Err(e) => return export_error(e),
+    };
+    let contents = stream::iter(pages).then(move |page| {
+        let qdrant = qdrant.clone();
+        let base_url = base_url.clone();
+        async move { page_content(qdrant.get_ref(), &page, &base_url).await }
+    });
+    let body = stream::once(async { Ok(header()) }).chain(contents);
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .streaming(text_stream(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_section(page: &str, title: &str) -> RetrievedPoint {
+        RetrievedPoint {
+            payload: [("page", page), ("title", title), ("url", page)]
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.into()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn section(line: i64, content: &str) -> Section {
+        Section {
+            title: String::new(),
+            slug: String::new(),
+            content: content.to_string(),
+            url: "documentation/guides".to_string(),
+            page: "documentation/guides".to_string(),
+            parent_sections: vec![],
+            parent_pages: vec![],
+            level: 1,
+            line,
+        }
+    }
+
+    #[test]
+    fn pages_are_listed_by_title() {
+        let point = first_section("documentation/guides", "Guides");
+        assert_eq!(
+            index_line(&point, "http://h").as_deref(),
+            Some("- [Guides](http://h/md/documentation/guides)\n")
+        );
+        // pages without a title before their first heading are listed by path
+        let point = first_section("documentation/faq", "");
+        assert_eq!(
+            index_line(&point, "http://h").as_deref(),
+            Some("- [documentation/faq](http://h/md/documentation/faq)\n")
+        );
+        assert_eq!(index_line(&RetrievedPoint::default(), "http://h"), None);
+    }
+
+    #[test]
+    fn pages_are_in_url_order() {
+        let mut points = vec![
+            first_section("documentation/guides", "Guides"),
+            first_section("articles", "Articles"),
+            first_section("documentation/faq", "FAQ"),
+        ];
+        sort_by_url(&mut points);
+        let pages: Vec<_> = points.iter().filter_map(|p| field(p, "page")).collect();
+        assert_eq!(pages, vec!["articles", "documentation/faq", "documentation/guides"]);
+    }
+
+    #[test]
+    fn sections_are_in_line_order() {
+        let sections = vec![
+            section(
+                12,
+                "## Second\n\nSee [docs](/documentation/foo/index.md#bar).",
+            ),
+            section(0, "# First"),
+        ];
+        assert_eq!(
+            page_text("documentation/guides", sections, "http://h"),
+            "\n---\n\nSource: http://h/md/documentation/guides\n\n\
+             # First\n## Second\n\nSee [docs](/md/documentation/foo?s=bar).\n"
+        );
+    }
+}
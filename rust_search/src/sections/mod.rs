@@ -1,5 +1,12 @@
 mod handler;
 mod links;
+mod llms;
 mod models;
+mod navigation;
+mod ranking;
+mod toc;
 
-pub use handler::md_handler;
+pub use handler::{ensure_line_index, md_handler, parse_points, query_by_filter};
+pub use llms::{llms_full_txt, llms_txt};
+pub use models::slugify_heading;
+pub use ranking::{SectionRanker, NEURAL_ENCODER};
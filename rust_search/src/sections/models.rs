@@ -1,11 +1,15 @@
 use std::collections::HashMap;
 
 use qdrant_client::qdrant::Value;
+use pulldown_cmark::html::push_html;
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+use pulldown_cmark_escape::{escape_href, escape_html};
 use qdrant_client::Payload;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use super::links::rewrite_links;
+use super::navigation::{NavLink, Navigation};
 
 pub fn slugify_heading(title: &str) -> String {
     let s = title.to_lowercase();
@@ -39,28 +43,201 @@ impl Section {
 pub struct SectionSearchResult {
     pub sections: Vec<Section>,
     pub sublinks: Option<Vec<String>>,
+    /// Sections left out to fit the size budget, rendered as links only
+    pub omitted: Vec<Section>,
+    /// Cursors of the next page, if the sections or sublinks didn't fit into this one
+    pub next: PageCursors,
+    /// Breadcrumbs and neighbouring sections, when browsing a page
+    pub navigation: Option<Navigation>,
+}
+
+/// Query parameter holding the line of the last section of the previous page.
+pub const OFFSET_PARAM: &str = "offset";
+/// Query parameter holding the scroll offset of the next page of sublinks.
+pub const SUBLINKS_OFFSET_PARAM: &str = "sublinks_offset";
+
+/// Positions in the sections and sublinks of a page, as passed in the query string.
+#[derive(Debug, Default, Clone)]
+pub struct PageCursors {
+    /// Sections start after this line
+    pub sections: Option<String>,
+    /// Sublinks start at this point id of the scroll
+    pub sublinks: Option<String>,
+}
+
+/// Percent-encode a query parameter value, keeping `/` readable.
+fn encode_query_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// URL of the current request with the query parameter `name` set to `value`.
+pub(super) fn url_with_param(
+    request_path: &str,
+    request_query: Option<&str>,
+    base_url: &str,
+    name: &str,
+    value: &str,
+) -> String {
+    let mut params: Vec<String> = request_query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|param| !param.is_empty() && param.split('=').next() != Some(name))
+        .map(str::to_string)
+        .collect();
+    params.push(format!("{}={}", name, encode_query_value(value)));
+    format!("{}{}?{}", base_url, request_path, params.join("&"))
+}
+
+/// Rough number of characters per LLM token, used to turn a token budget into characters.
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// A section as rendered in the JSON output, with links rewritten for the `/md/` service.
+#[derive(Serialize)]
+struct SectionView<'a> {
+    title: &'a str,
+    slug: &'a str,
+    level: i64,
+    line: i64,
+    url: &'a str,
+    page: &'a str,
+    parent_sections: &'a [String],
+    parent_pages: &'a [String],
+    content: String,
+}
+
+/// A section left out of the output, with the link to read it.
+#[derive(Serialize)]
+struct OmittedView<'a> {
+    title: &'a str,
+    slug: &'a str,
+    link: String,
+}
+
+#[derive(Serialize)]
+struct SectionSearchView<'a> {
+    up_url: String,
+    sections: Vec<SectionView<'a>>,
+    omitted: Vec<OmittedView<'a>>,
+    sublinks: Option<Vec<String>>,
+    next_sections: Option<String>,
+    next_sublinks: Option<String>,
+    breadcrumbs: Option<Vec<NavLink>>,
+    previous: Option<NavLink>,
+    next: Option<NavLink>,
 }
 
 impl SectionSearchResult {
-    /// Render the result as markdown, mirroring the Python implementation.
+    /// Keep only as many sections as fit into `max_chars` characters of the markdown output.
     ///
-    /// `request_path` is the full request path (e.g. `/md/documentation/guides`).
-    /// `request_query` is the raw query string (e.g. `q=foo&s=bar`), if any.
-    /// `base_url` is `{scheme}://{host}` (e.g. `https://example.com`).
-    pub fn to_markdown(
-        &self,
+    /// Sections are picked by priority: the `requested` section and its ancestors first, then
+    /// the others by level, so that parents come before their children. A section is never
+    /// kept without its ancestors. Sections that don't fit are moved to `omitted`, whose
+    /// links count against the budget like the rest of the page.
+    pub fn apply_budget(
+        &mut self,
+        max_chars: usize,
+        requested: Option<&str>,
         request_path: &str,
         request_query: Option<&str>,
         base_url: &str,
-    ) -> String {
+    ) {
+        let rendered_size =
+            |result: &Self| result.to_markdown(request_path, request_query, base_url).chars().count();
+        if rendered_size(self) <= max_chars {
+            return;
+        }
+        let mut by_priority = std::mem::take(&mut self.sections);
+        let requested_path: Vec<(String, Vec<String>)> = by_priority
+            .iter()
+            .filter(|s| Some(s.slug.as_str()) == requested)
+            .map(|s| (s.url.clone(), s.parent_sections.clone()))
+            .collect();
+        by_priority.sort_by_key(|s| {
+            let on_requested_path = requested_path
+                .iter()
+                .any(|(url, parents)| *url == s.url && parents.contains(&s.slug));
+            (!on_requested_path, s.level, s.line)
+        });
+
+        // start from the page with every section omitted, keeping one replaces its link
+        let omitted_before = self.omitted.len();
+        self.omitted.extend(by_priority);
+        let mut size = rendered_size(self);
+        let by_priority = self.omitted.split_off(omitted_before);
+
+        for section in by_priority {
+            let has_ancestors = !self.omitted.iter().any(|omitted| {
+                omitted.url == section.url
+                    && omitted.slug != section.slug
+                    && section.parent_sections.contains(&omitted.slug)
+            });
+            let link = Self::omitted_item(&section, request_path).chars().count();
+            // sections are joined by newlines
+            let content = rewrite_links(&section.content).chars().count() + 1;
+            if has_ancestors && size + content <= max_chars.saturating_add(link) {
+                size = size + content - link;
+                self.sections.push(section);
+            } else {
+                self.omitted.push(section);
+            }
+        }
+        // the up link depends on the kept sections, the last one kept has no kept descendants
+        while rendered_size(self) > max_chars {
+            match self.sections.pop() {
+                Some(section) => self.omitted.push(section),
+                None => break,
+            }
+        }
+        self.omitted.sort_by(|a, b| (&a.url, a.line).cmp(&(&b.url, b.line)));
+    }
+
+    /// Markdown list item linking to an omitted section.
+    fn omitted_item(section: &Section, request_path: &str) -> String {
+        format!(
+            "- [{}]({})\n",
+            section.title,
+            Self::section_link(section, request_path)
+        )
+    }
+
+    /// Link to a section, relative if it is on the requested page.
+    pub(super) fn section_link(section: &Section, request_path: &str) -> String {
+        if request_path.trim_end_matches('/') == format!("/md/{}", section.page) {
+            format!("?s={}", section.slug)
+        } else {
+            format!("/md/{}?s={}", section.page, section.slug)
+        }
+    }
+
+    pub(super) fn sorted_sections(&self) -> Vec<&Section> {
         let mut sections: Vec<&Section> = self.sections.iter().collect();
         sections.sort_by(|a, b| (&a.url, a.line).cmp(&(&b.url, b.line)));
+        sections
+    }
 
-        // Compute up_url
+    /// URL of the parent section, or of the parent page if no section was requested.
+    pub(super) fn up_url(
+        sections: &[&Section],
+        request_path: &str,
+        request_query: Option<&str>,
+        base_url: &str,
+    ) -> String {
         let stripped = request_path.trim_matches('/');
         let segments: Vec<&str> = stripped.split('/').collect();
 
-        let has_section_query = request_query.map(|q| q.contains("s=")).unwrap_or(false);
+        let has_section_query = request_query.is_some_and(|q| {
+            q.split('&')
+                .any(|pair| pair.split('=').next() == Some("s"))
+        });
 
         let (up_path, up_query): (String, Option<String>) =
             if has_section_query
@@ -80,11 +257,55 @@ impl SectionSearchResult {
                 (format!("/{}", up_segments.join("/")), None)
             };
 
-        let up_url = if let Some(q) = up_query {
+        if let Some(q) = up_query {
             format!("{}{}?{}", base_url, up_path, q)
         } else {
             format!("{}{}", base_url, up_path)
+        }
+    }
+
+    /// Sublinks are page paths like "documentation/guides/something",
+    /// prefixed with /md/ to match our routing.
+    fn sublink_urls(&self, base_url: &str) -> Option<Vec<String>> {
+        self.sublinks.as_ref().map(|sublinks| {
+            sublinks
+                .iter()
+                .map(|sub| format!("{}/md/{}", base_url, sub))
+                .collect()
+        })
+    }
+
+    /// URLs of the next page of sections and of sublinks, if there is one.
+    pub(super) fn next_urls(
+        &self,
+        request_path: &str,
+        request_query: Option<&str>,
+        base_url: &str,
+    ) -> (Option<String>, Option<String>) {
+        let next_url = |name: &str, cursor: &Option<String>| {
+            cursor.as_deref().map(|value| {
+                url_with_param(request_path, request_query, base_url, name, value)
+            })
         };
+        (
+            next_url(OFFSET_PARAM, &self.next.sections),
+            next_url(SUBLINKS_OFFSET_PARAM, &self.next.sublinks),
+        )
+    }
+
+    /// Render the result as markdown, mirroring the Python implementation.
+    ///
+    /// `request_path` is the full request path (e.g. `/md/documentation/guides`).
+    /// `request_query` is the raw query string (e.g. `q=foo&s=bar`), if any.
+    /// `base_url` is `{scheme}://{host}` (e.g. `https://example.com`).
+    pub fn to_markdown(
+        &self,
+        request_path: &str,
+        request_query: Option<&str>,
+        base_url: &str,
+    ) -> String {
+        let sections = self.sorted_sections();
+        let up_url = Self::up_url(&sections, request_path, request_query, base_url);
 
         let sections_text: String = sections
             .iter()
@@ -92,19 +313,414 @@ impl SectionSearchResult {
             .collect::<Vec<_>>()
             .join("\n");
 
-        let mut result = format!("Read one level up: {}\n\n{}", up_url, sections_text);
+        let navigation = self
+            .navigation
+            .as_ref()
+            .map(|nav| nav.to_markdown(base_url))
+            .unwrap_or_default();
+        let mut result = format!(
+            "Read one level up: {}\n{}\n{}",
+            up_url, navigation, sections_text
+        );
+        let (next_sections, next_sublinks) = self.next_urls(request_path, request_query, base_url);
+
+        if let Some(next) = next_sections {
+            result.push_str(&format!("\nNext page of sections: {}\n", next));
+        }
 
-        if let Some(ref sublinks) = self.sublinks {
+        if !self.omitted.is_empty() {
+            result.push_str("\n## Sections not included\n\n");
+            for section in &self.omitted {
+                result.push_str(&Self::omitted_item(section, request_path));
+            }
+        }
+
+        if let Some(sublinks) = self.sublink_urls(base_url) {
             if !sublinks.is_empty() {
                 result.push_str("\n## Subsites to Search\n");
                 for sub in sublinks {
-                    // sublinks are page paths like "documentation/guides/something"
-                    // prefix with /md/ to match our routing
-                    result.push_str(&format!("\n{}/md/{}", base_url, sub));
+                    result.push_str(&format!("\n{}", sub));
                 }
             }
         }
+        if let Some(next) = next_sublinks {
+            result.push_str(&format!("\n\nNext page of subsites: {}", next));
+        }
 
         result
     }
+
+    /// Render the result as JSON, with the same arguments as [`Self::to_markdown`].
+    pub fn to_json(&self, request_path: &str, request_query: Option<&str>, base_url: &str) -> String {
+        let sections = self.sorted_sections();
+        let (next_sections, next_sublinks) = self.next_urls(request_path, request_query, base_url);
+        let view = SectionSearchView {
+            up_url: Self::up_url(&sections, request_path, request_query, base_url),
+            sections: sections
+                .iter()
+                .map(|s| SectionView {
+                    title: &s.title,
+                    slug: &s.slug,
+                    level: s.level,
+                    line: s.line,
+                    url: &s.url,
+                    page: &s.page,
+                    parent_sections: &s.parent_sections,
+                    parent_pages: &s.parent_pages,
+                    content: rewrite_links(&s.content),
+                })
+                .collect(),
+            omitted: self
+                .omitted
+                .iter()
+                .map(|s| OmittedView {
+                    title: &s.title,
+                    slug: &s.slug,
+                    link: Self::section_link(s, request_path),
+                })
+                .collect(),
+            sublinks: self.sublink_urls(base_url),
+            next_sections,
+            next_sublinks,
+            breadcrumbs: self.navigation.as_ref().map(|nav| nav.breadcrumbs(base_url)),
+            previous: self.navigation.as_ref().and_then(Navigation::previous_link),
+            next: self.navigation.as_ref().and_then(Navigation::next_link),
+        };
+        serde_json::to_string(&view).expect("Failed to serialize sections")
+    }
+
+    /// Render the result as a minimal HTML page, with the same arguments as [`Self::to_markdown`].
+    pub fn to_html(&self, request_path: &str, request_query: Option<&str>, base_url: &str) -> String {
+        let sections = self.sorted_sections();
+        let up_url = Self::up_url(&sections, request_path, request_query, base_url);
+        let title = sections.first().map_or(request_path, |s| s.title.as_str());
+
+        let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>");
+        escape_html(&mut html, title).unwrap();
+        html.push_str("</title>\n</head>\n<body>\n<nav><a href=\"");
+        escape_href(&mut html, &up_url).unwrap();
+        html.push_str("\">Read one level up</a></nav>\n");
+        if let Some(navigation) = &self.navigation {
+            let links = navigation
+                .breadcrumbs(base_url)
+                .into_iter()
+                .chain(navigation.previous_link().map(|link| NavLink {
+                    title: format!("Previous: {}", link.title),
+                    ..link
+                }))
+                .chain(navigation.next_link().map(|link| NavLink {
+                    title: format!("Next: {}", link.title),
+                    ..link
+                }));
+            html.push_str("<nav>\n<ul>\n");
+            for link in links {
+                html.push_str("<li><a href=\"");
+                escape_href(&mut html, &link.link).unwrap();
+                html.push_str("\">");
+                escape_html(&mut html, &link.title).unwrap();
+                html.push_str("</a></li>\n");
+            }
+            html.push_str("</ul>\n</nav>\n");
+        }
+        html.push_str("<main>\n");
+
+        for section in &sections {
+            let content = rewrite_links(&section.content);
+            push_html(
+                &mut html,
+                escape_raw_html(Parser::new_ext(&content, Options::ENABLE_TABLES)),
+            );
+        }
+        html.push_str("</main>\n");
+        let (next_sections, next_sublinks) = self.next_urls(request_path, request_query, base_url);
+        if let Some(next) = next_sections {
+            html.push_str("<nav><a href=\"");
+            escape_href(&mut html, &next).unwrap();
+            html.push_str("\">Next page of sections</a></nav>\n");
+        }
+
+        if !self.omitted.is_empty() {
+            html.push_str("<nav>\n<h2>Sections not included</h2>\n<ul>\n");
+            for section in &self.omitted {
+                html.push_str("<li><a href=\"");
+                escape_href(&mut html, &Self::section_link(section, request_path)).unwrap();
+                html.push_str("\">");
+                escape_html(&mut html, &section.title).unwrap();
+                html.push_str("</a></li>\n");
+            }
+            html.push_str("</ul>\n</nav>\n");
+        }
+
+        if let Some(sublinks) = self.sublink_urls(base_url) {
+            if !sublinks.is_empty() {
+                html.push_str("<nav>\n<h2>Subsites to Search</h2>\n<ul>\n");
+                for sub in sublinks {
+                    html.push_str("<li><a href=\"");
+                    escape_href(&mut html, &sub).unwrap();
+                    html.push_str("\">");
+                    escape_html(&mut html, &sub).unwrap();
+                    html.push_str("</a></li>\n");
+                }
+                html.push_str("</ul>\n</nav>\n");
+            }
+        }
+        if let Some(next) = next_sublinks {
+            html.push_str("<nav><a href=\"");
+            escape_href(&mut html, &next).unwrap();
+            html.push_str("\">Next page of subsites</a></nav>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+/// Markdown events with raw HTML turned into text and script links disabled, so that section
+/// content can't run scripts on the origin of the service.
+fn escape_raw_html<'a>(events: impl Iterator<Item = Event<'a>>) -> impl Iterator<Item = Event<'a>> {
+    events.map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if is_script_url(&dest_url) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: CowStr::from("#"),
+            title,
+            id,
+        }),
+        event => event,
+    })
+}
+
+fn is_script_url(url: &str) -> bool {
+    let url = url.trim_start().to_ascii_lowercase();
+    ["javascript:", "vbscript:", "data:"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(title: &str, slug: &str, line: i64, parent_sections: &[&str]) -> Section {
+        Section {
+            title: title.to_string(),
+            slug: slug.to_string(),
+            content: format!("## {}\n\nSee [docs](/documentation/foo/index.md#bar).\n", title),
+            url: "documentation/guides".to_string(),
+            page: "documentation/guides".to_string(),
+            parent_sections: parent_sections.iter().map(|s| s.to_string()).collect(),
+            parent_pages: vec!["documentation".to_string(), "documentation/guides".to_string()],
+            level: parent_sections.len() as i64,
+            line,
+        }
+    }
+
+    fn result() -> SectionSearchResult {
+        SectionSearchResult {
+            sections: vec![
+                section("Install", "install", 20, &["guides", "install"]),
+                section("Guides", "guides", 1, &["guides"]),
+            ],
+            sublinks: Some(vec!["documentation/guides/sub".to_string()]),
+            omitted: vec![],
+            next: PageCursors::default(),
+            navigation: None,
+        }
+    }
+
+    #[test]
+    fn markdown_output() {
+        let markdown = result().to_markdown("/md/documentation/guides", None, "http://h");
+        assert!(markdown.starts_with("Read one level up: http://h/md/documentation\n\n## Guides"));
+        assert!(markdown.contains("[docs](/md/documentation/foo?s=bar)"));
+        assert!(markdown.ends_with("## Subsites to Search\n\nhttp://h/md/documentation/guides/sub"));
+    }
+
+    #[test]
+    fn json_output() {
+        let json: serde_json::Value = serde_json::from_str(&result().to_json(
+            "/md/documentation/guides",
+            Some("s=install"),
+            "http://h",
+        ))
+        .unwrap();
+        // sorted by line, the first section has a single parent section
+        assert_eq!(json["up_url"], "http://h/md/documentation");
+        assert_eq!(json["sections"][0]["slug"], "guides");
+        assert_eq!(json["sections"][1]["level"], 2);
+        assert_eq!(json["sections"][1]["parent_sections"][0], "guides");
+        assert!(json["sections"][1]["content"]
+            .as_str()
+            .unwrap()
+            .contains("(/md/documentation/foo?s=bar)"));
+        assert_eq!(json["sublinks"][0], "http://h/md/documentation/guides/sub");
+    }
+
+    #[test]
+    fn html_output() {
+        let html = result().to_html("/md/documentation/guides", None, "http://h");
+        assert!(html.contains("<title>Guides</title>"));
+        assert!(html.contains("<a href=\"http://h/md/documentation\">Read one level up</a>"));
+        assert!(html.contains("<h2>Install</h2>"));
+        assert!(html.contains("<a href=\"/md/documentation/foo?s=bar\">docs</a>"));
+        assert!(html.contains("<li><a href=\"http://h/md/documentation/guides/sub\">"));
+    }
+
+    #[test]
+    fn up_url_of_budgeted_section() {
+        let result = result();
+        let install: Vec<&Section> = result.sections.iter().filter(|s| s.slug == "install").collect();
+        let up_url = |query| {
+            SectionSearchResult::up_url(&install, "/md/documentation/guides", Some(query), "http://h")
+        };
+        assert_eq!(up_url("max_tokens=500"), "http://h/md/documentation");
+        assert_eq!(up_url("max_tokens=500&s=install"), "http://h/md/documentation/guides?s=guides");
+    }
+
+    #[test]
+    fn raw_html_is_escaped() {
+        let mut result = result();
+        result.sections[1].content = "## Guides\n\n<script>alert(1)</script>\n\n\
+                                      Text <img src=x onerror=alert(1)> and \
+                                      [a link](javascript:alert(1))\n"
+            .to_string();
+        let html = result.to_html("/md/documentation/guides", None, "http://h");
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(html.contains("Text &lt;img src=x onerror=alert(1)&gt; and"));
+        assert!(html.contains("<a href=\"#\">a link</a>"));
+    }
+
+    fn sized_section(slug: &str, parent_sections: &[&str], line: i64, size: usize) -> Section {
+        Section {
+            title: slug.to_uppercase(),
+            slug: slug.to_string(),
+            content: "x".repeat(size),
+            url: "documentation/guides".to_string(),
+            page: "documentation/guides".to_string(),
+            parent_sections: parent_sections.iter().map(|s| s.to_string()).collect(),
+            parent_pages: vec![],
+            level: parent_sections.len() as i64,
+            line,
+        }
+    }
+
+    fn sized_result() -> SectionSearchResult {
+        SectionSearchResult {
+            sections: vec![
+                sized_section("top", &["top"], 1, 400),
+                sized_section("child", &["top", "child"], 10, 300),
+                sized_section("grandchild", &["top", "child", "grandchild"], 20, 100),
+                sized_section("other-child", &["top", "other-child"], 30, 500),
+            ],
+            sublinks: None,
+            omitted: vec![],
+            next: PageCursors::default(),
+            navigation: None,
+        }
+    }
+
+    fn slugs(sections: &[Section]) -> Vec<&str> {
+        sections.iter().map(|s| s.slug.as_str()).collect()
+    }
+
+    const PATH: &str = "/md/documentation/guides";
+
+    /// Apply a budget of the markdown size without budget plus `extra` characters.
+    fn budgeted(extra: isize, requested: Option<&str>) -> SectionSearchResult {
+        let mut result = sized_result();
+        let unlimited = result.to_markdown(PATH, None, "http://h").chars().count();
+        let max_chars = (unlimited as isize + extra) as usize;
+        result.apply_budget(max_chars, requested, PATH, None, "http://h");
+        assert!(result.to_markdown(PATH, None, "http://h").chars().count() <= max_chars);
+        result
+    }
+
+    #[test]
+    fn budget_counts_the_rendered_page() {
+        let result = budgeted(0, None);
+        assert_eq!(slugs(&result.sections), vec!["top", "child", "grandchild", "other-child"]);
+        // lower levels are omitted first
+        let result = budgeted(-1, None);
+        assert_eq!(slugs(&result.sections), vec!["top", "child", "other-child"]);
+        assert_eq!(slugs(&result.omitted), vec!["grandchild"]);
+    }
+
+    #[test]
+    fn budget_prefers_requested_section() {
+        let result = budgeted(-300, Some("other-child"));
+        assert_eq!(slugs(&result.sections), vec!["top", "other-child"]);
+        assert_eq!(slugs(&result.omitted), vec!["child", "grandchild"]);
+    }
+
+    #[test]
+    fn budget_keeps_ancestors() {
+        // the grandchild would fit, but not without the child
+        let result = budgeted(-700, None);
+        assert_eq!(slugs(&result.sections), vec!["top"]);
+        assert_eq!(slugs(&result.omitted), vec!["child", "grandchild", "other-child"]);
+    }
+
+    #[test]
+    fn budget_of_huge_token_counts() {
+        let mut result = sized_result();
+        result.apply_budget(usize::MAX, None, PATH, None, "http://h");
+        assert!(result.omitted.is_empty());
+    }
+
+    #[test]
+    fn omitted_sections_are_linked() {
+        let mut result = sized_result();
+        result.apply_budget(0, None, PATH, None, "http://h");
+        let markdown = result.to_markdown(PATH, None, "http://h");
+        assert!(markdown.contains("## Sections not included\n\n- [TOP](?s=top)\n- [CHILD](?s=child)\n"));
+        let markdown = result.to_markdown("/md/documentation", Some("q=foo"), "http://h");
+        assert!(markdown.contains("- [CHILD](/md/documentation/guides?s=child)\n"));
+    }
+
+    #[test]
+    fn next_page_links() {
+        let mut result = result();
+        result.next = PageCursors {
+            sections: Some("42".to_string()),
+            sublinks: Some("5f0b7d0c-8d5a-4c2e-9a6b-1f2e3d4c5b6a".to_string()),
+        };
+        let markdown = result.to_markdown("/md/documentation/guides", Some("offset=1&format=md"), "http://h");
+        assert!(markdown.contains(
+            "Next page of sections: http://h/md/documentation/guides?format=md&offset=42\n"
+        ));
+        assert!(markdown.ends_with(
+            "Next page of subsites: http://h/md/documentation/guides?offset=1&format=md&sublinks_offset=5f0b7d0c-8d5a-4c2e-9a6b-1f2e3d4c5b6a"
+        ));
+        let json: serde_json::Value = serde_json::from_str(&result.to_json("/md/x", None, "http://h")).unwrap();
+        assert_eq!(json["next_sections"], "http://h/md/x?offset=42");
+    }
+
+    #[test]
+    fn query_values_are_encoded() {
+        assert_eq!(
+            url_with_param("/md/a", Some("q=x"), "", SUBLINKS_OFFSET_PARAM, "a b&c/d"),
+            "/md/a?q=x&sublinks_offset=a%20b%26c/d"
+        );
+    }
+
+    #[test]
+    fn navigation_output() {
+        let mut result = result();
+        result.navigation = Some(Navigation::new("documentation/guides", None, &[]));
+        let markdown = result.to_markdown("/md/documentation/guides", None, "http://h");
+        assert!(markdown.starts_with(
+            "Read one level up: http://h/md/documentation\n\
+             Breadcrumbs: [documentation](http://h/md/documentation) > \
+             [guides](http://h/md/documentation/guides)\n\n## Guides"
+        ));
+        let json: serde_json::Value =
+            serde_json::from_str(&result.to_json("/md/documentation/guides", None, "http://h")).unwrap();
+        assert_eq!(json["breadcrumbs"][1]["link"], "http://h/md/documentation/guides");
+        assert!(json["next"].is_null());
+    }
 }
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use qdrant_client::qdrant::Value;
+use qdrant_client::Payload;
+use serde::{Deserialize, Serialize};
+
+/// Payload fields of a section needed for navigation, without its content.
+pub const HEADING_FIELDS: &[&str] = &["title", "slug", "level", "line", "parent_sections"];
+
+/// A section heading of a page outline.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Heading {
+    pub title: String,
+    pub slug: String,
+    pub level: i64,
+    pub line: i64,
+    pub parent_sections: Vec<String>,
+}
+
+impl Heading {
+    pub fn from_payload(payload: HashMap<String, Value>) -> Option<Self> {
+        Payload::from(payload).deserialize().ok()
+    }
+}
+
+/// Where a browsed page or section sits in the documentation.
+#[derive(Debug, Clone)]
+pub struct Navigation {
+    /// The page and its parent pages, outermost first
+    pub pages: Vec<String>,
+    /// The parent sections of the requested section, outermost first
+    pub sections: Vec<Heading>,
+    /// The section read before the requested one
+    pub previous: Option<Heading>,
+    /// The section read after the requested one and its subsections
+    pub next: Option<Heading>,
+}
+
+/// A rendered breadcrumb or neighbour link.
+#[derive(Debug, Serialize)]
+pub struct NavLink {
+    pub title: String,
+    pub link: String,
+}
+
+impl Navigation {
+    /// Navigation of `page`, or of its section `requested` within the page `outline`.
+    ///
+    /// Previous and next follow the reading order: they are the closest sections before
+    /// and after the requested one that are not nested deeper than it, so the next section
+    /// skips the requested section's own subsections.
+    pub fn new(page: &str, requested: Option<&str>, outline: &[Heading]) -> Self {
+        let pages = page
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .scan(String::new(), |path, segment| {
+                if !path.is_empty() {
+                    path.push('/');
+                }
+                path.push_str(segment);
+                Some(path.clone())
+            })
+            .collect();
+
+        let mut outline: Vec<&Heading> = outline.iter().collect();
+        outline.sort_by_key(|h| h.line);
+        let current = requested.and_then(|slug| outline.iter().position(|h| h.slug == slug));
+        let Some(idx) = current else {
+            return Self {
+                pages,
+                sections: vec![],
+                previous: None,
+                next: None,
+            };
+        };
+        let heading = outline[idx];
+
+        let parents = &heading.parent_sections[..heading.parent_sections.len().saturating_sub(1)];
+        let sections = parents
+            .iter()
+            .map(|slug| match outline.iter().find(|h| &h.slug == slug) {
+                Some(parent) => (*parent).clone(),
+                None => Heading {
+                    title: slug.clone(),
+                    slug: slug.clone(),
+                    level: 0,
+                    line: 0,
+                    parent_sections: vec![],
+                },
+            })
+            .collect();
+        let not_deeper = |h: &&&Heading| h.level <= heading.level;
+        Self {
+            pages,
+            sections,
+            previous: outline[..idx].iter().rev().find(not_deeper).map(|h| (*h).clone()),
+            next: outline[idx + 1..].iter().find(not_deeper).map(|h| (*h).clone()),
+        }
+    }
+
+    /// Links to the parent pages and sections, outermost first.
+    pub fn breadcrumbs(&self, base_url: &str) -> Vec<NavLink> {
+        let pages = self.pages.iter().map(|page| NavLink {
+            title: page.rsplit('/').next().unwrap_or(page).to_string(),
+            link: format!("{}/md/{}", base_url, page),
+        });
+        let sections = self.sections.iter().map(|h| self.link_to(h));
+        pages.chain(sections).collect()
+    }
+
+    /// Link to a section of the navigated page, relative to it.
+    pub fn link_to(&self, heading: &Heading) -> NavLink {
+        NavLink {
+            title: if heading.title.is_empty() {
+                heading.slug.clone()
+            } else {
+                heading.title.clone()
+            },
+            link: format!("?s={}", heading.slug),
+        }
+    }
+
+    pub fn previous_link(&self) -> Option<NavLink> {
+        self.previous.as_ref().map(|h| self.link_to(h))
+    }
+
+    pub fn next_link(&self) -> Option<NavLink> {
+        self.next.as_ref().map(|h| self.link_to(h))
+    }
+
+    /// Breadcrumbs and neighbours as markdown lines.
+    pub fn to_markdown(&self, base_url: &str) -> String {
+        let crumbs: Vec<String> = self
+            .breadcrumbs(base_url)
+            .iter()
+            .map(|crumb| format!("[{}]({})", crumb.title, crumb.link))
+            .collect();
+        let mut result = String::new();
+        if !crumbs.is_empty() {
+            result.push_str(&format!("Breadcrumbs: {}\n", crumbs.join(" > ")));
+        }
+        if let Some(previous) = self.previous_link() {
+            result.push_str(&format!("Previous section: [{}]({})\n", previous.title, previous.link));
+        }
+        if let Some(next) = self.next_link() {
+            result.push_str(&format!("Next section: [{}]({})\n", next.title, next.link));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heading(slug: &str, line: i64, parent_sections: &[&str]) -> Heading {
+        Heading {
+            title: slug.to_uppercase(),
+            slug: slug.to_string(),
+            level: parent_sections.len() as i64,
+            line,
+            parent_sections: parent_sections.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn outline() -> Vec<Heading> {
+        vec![
+            heading("docker", 20, &["guides", "install", "docker"]),
+            heading("guides", 0, &["guides"]),
+            heading("install", 10, &["guides", "install"]),
+            heading("faq", 30, &["guides", "faq"]),
+            heading("pip", 25, &["guides", "install", "pip"]),
+        ]
+    }
+
+    fn slug(heading: &Option<Heading>) -> Option<&str> {
+        heading.as_ref().map(|h| h.slug.as_str())
+    }
+
+    #[test]
+    fn neighbours_follow_reading_order() {
+        let nav = Navigation::new("documentation/guides", Some("install"), &outline());
+        assert_eq!(slug(&nav.previous), Some("guides"));
+        // the subsections of install are skipped
+        assert_eq!(slug(&nav.next), Some("faq"));
+
+        let nav = Navigation::new("documentation/guides", Some("docker"), &outline());
+        assert_eq!(slug(&nav.previous), Some("install"));
+        assert_eq!(slug(&nav.next), Some("pip"));
+
+        let nav = Navigation::new("documentation/guides", Some("faq"), &outline());
+        assert_eq!(slug(&nav.next), None);
+    }
+
+    #[test]
+    fn breadcrumbs_of_pages_and_sections() {
+        let nav = Navigation::new("documentation/guides", Some("docker"), &outline());
+        assert_eq!(
+            nav.to_markdown("http://h"),
+            "Breadcrumbs: [documentation](http://h/md/documentation) > \
+             [guides](http://h/md/documentation/guides) > [GUIDES](?s=guides) > \
+             [INSTALL](?s=install)\n\
+             Previous section: [INSTALL](?s=install)\n\
+             Next section: [PIP](?s=pip)\n"
+        );
+    }
+
+    #[test]
+    fn page_without_section() {
+        let nav = Navigation::new("documentation/guides", None, &[]);
+        assert_eq!(nav.pages, vec!["documentation", "documentation/guides"]);
+        assert!(nav.sections.is_empty() && nav.previous.is_none() && nav.next.is_none());
+    }
+}
@@ -0,0 +1,262 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use ndarray::{Array2, CowArray};
+use ort::tensor::OrtOwnedTensor;
+use ort::{Environment, Session, SessionBuilder, Value as OrtValue};
+use qdrant_client::qdrant::{
+    Condition, Document, Filter, Fusion, PrefetchQueryBuilder, Query, QueryPoints,
+    QueryPointsBuilder, VectorInput,
+};
+use rust_tokenizers::tokenizer::{BertTokenizer, Tokenizer, TruncationStrategy};
+use rust_tokenizers::vocab::Vocab;
+
+use crate::common::{env_or, get_embeddings, MAX_TOKENS, SECTION_COLLECTION_NAME};
+
+pub const NEURAL_ENCODER: &str = "sentence-transformers/all-MiniLM-L6-v2";
+const BM25_MODEL: &str = "qdrant/bm25";
+
+/// Where the query vector of a section search comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryEmbedding {
+    /// Inference by Qdrant with `NEURAL_ENCODER`
+    Server,
+    /// The ONNX model loaded by the service, which is the same encoder
+    Local,
+}
+
+#[derive(Debug, Clone)]
+pub struct RankingConfig {
+    /// Where the query is embedded (`SECTIONS_EMBEDDING`, `server` or `local`)
+    pub embedding: QueryEmbedding,
+    /// Fuse the dense results with BM25 results (`SECTIONS_HYBRID`), embedded by Qdrant
+    pub hybrid: bool,
+    /// Name of the sparse BM25 vector of the collection (`SECTIONS_SPARSE_VECTOR`)
+    pub sparse_vector: String,
+    /// Candidates of each search that are fused (`SECTIONS_PREFETCH_LIMIT`)
+    pub prefetch_limit: u64,
+    /// Candidates passed to the reranker (`SECTIONS_RERANK_TOP_K`)
+    pub rerank_top_k: u64,
+}
+
+impl RankingConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let embedding = match std::env::var("SECTIONS_EMBEDDING").as_deref() {
+            Err(_) | Ok("server") => QueryEmbedding::Server,
+            Ok("local") => QueryEmbedding::Local,
+            Ok(other) => anyhow::bail!("Unknown section query embedding `{other}`"),
+        };
+        let hybrid = env_or("SECTIONS_HYBRID", false);
+        // the BM25 query vector is always left to Qdrant
+        if hybrid && embedding == QueryEmbedding::Local {
+            anyhow::bail!(
+                "SECTIONS_HYBRID needs inference by Qdrant for BM25, it can't be used with SECTIONS_EMBEDDING=local"
+            );
+        }
+        Ok(Self {
+            embedding,
+            hybrid,
+            sparse_vector: env_or("SECTIONS_SPARSE_VECTOR", "bm25".to_string()),
+            prefetch_limit: env_or("SECTIONS_PREFETCH_LIMIT", 20_u64).max(1),
+            rerank_top_k: env_or("SECTIONS_RERANK_TOP_K", 20_u64).max(1),
+        })
+    }
+}
+
+/// Cross-encoder scoring how well a text answers the query.
+pub struct Reranker {
+    tokenizer: BertTokenizer,
+    session: Session,
+}
+
+impl Reranker {
+    /// Relevance score of every text for `query`, higher is better.
+    pub fn scores(&self, query: &str, texts: &[&str]) -> anyhow::Result<Vec<f32>> {
+        if texts.is_empty() {
+            return Ok(vec![]);
+        }
+        let encodings: Vec<_> = texts
+            .iter()
+            .map(|text| {
+                self.tokenizer.encode(
+                    query,
+                    Some(text),
+                    MAX_TOKENS,
+                    &TruncationStrategy::OnlySecond,
+                    0,
+                )
+            })
+            .collect();
+        let pad_id = self
+            .tokenizer
+            .vocab()
+            .token_to_id(self.tokenizer.vocab().get_pad_value());
+        let max_len = encodings.iter().map(|e| e.token_ids.len()).max().unwrap_or(0);
+        let shape = (encodings.len(), max_len);
+        let mut token_ids = Array2::from_elem(shape, pad_id);
+        let mut attentions = Array2::zeros(shape);
+        let mut type_ids = Array2::zeros(shape);
+        for (row, encoding) in encodings.iter().enumerate() {
+            for (col, (id, segment)) in encoding
+                .token_ids
+                .iter()
+                .zip(&encoding.segment_ids)
+                .enumerate()
+            {
+                token_ids[[row, col]] = *id;
+                attentions[[row, col]] = 1_i64;
+                type_ids[[row, col]] = *segment as i64;
+            }
+        }
+        let alloc = self.session.allocator();
+        let token_ids = CowArray::from(token_ids.into_dyn());
+        let attentions = CowArray::from(attentions.into_dyn());
+        let type_ids = CowArray::from(type_ids.into_dyn());
+        let outputs = self.session.run(vec![
+            OrtValue::from_array(alloc, &token_ids)?,
+            OrtValue::from_array(alloc, &attentions)?,
+            OrtValue::from_array(alloc, &type_ids)?,
+        ])?;
+        let output: OrtOwnedTensor<f32, _> = outputs[0].try_extract()?;
+        let logits: Vec<f32> = output.view().iter().copied().collect();
+        // the logits are either (batch,) or (batch, 1), the first one is the relevance
+        let per_row = logits.len() / texts.len();
+        Ok(logits.into_iter().step_by(per_row.max(1)).collect())
+    }
+}
+
+/// Keep the `limit` items with the highest scores, best first.
+pub fn order_by_scores<T>(items: Vec<T>, scores: &[f32], limit: usize) -> Vec<T> {
+    let mut scored: Vec<(f32, T)> = scores.iter().copied().zip(items).collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().take(limit).map(|(_, item)| item).collect()
+}
+
+/// How section queries are embedded, fused and reranked.
+pub struct SectionRanker {
+    pub config: RankingConfig,
+    pub reranker: Option<Reranker>,
+}
+
+impl SectionRanker {
+    /// Read the config and load the cross-encoder from `SECTIONS_RERANK_MODEL`, if set.
+    ///
+    /// The cross-encoder uses the vocabulary at `SECTIONS_RERANK_VOCAB` (default: `vocab.txt`).
+    pub fn from_env(environment: &Arc<Environment>) -> anyhow::Result<Self> {
+        let reranker = match std::env::var("SECTIONS_RERANK_MODEL") {
+            Ok(path) => {
+                let vocab = env_or("SECTIONS_RERANK_VOCAB", "vocab.txt".to_string());
+                let tokenizer = BertTokenizer::from_file(&vocab, true, false)
+                    .with_context(|| format!("Failed to load reranker vocabulary {vocab}"))?;
+                let session = SessionBuilder::new(environment)?
+                    .with_model_from_file(&path)
+                    .with_context(|| format!("Failed to load reranker model {path}"))?;
+                Some(Reranker { tokenizer, session })
+            }
+            Err(_) => None,
+        };
+        Ok(Self {
+            config: RankingConfig::from_env()?,
+            reranker,
+        })
+    }
+
+    /// Number of candidates to fetch so that `limit` are left after reranking.
+    pub fn candidates(&self, limit: u64) -> u64 {
+        match self.reranker {
+            Some(_) => self.config.rerank_top_k.max(limit),
+            None => limit,
+        }
+    }
+
+    /// The dense query vector, embedded locally or left to Qdrant.
+    pub fn dense_input(
+        &self,
+        query: &str,
+        embedder: (&BertTokenizer, &Session),
+    ) -> anyhow::Result<VectorInput> {
+        Ok(match self.config.embedding {
+            QueryEmbedding::Server => VectorInput::from(Document::new(query, NEURAL_ENCODER)),
+            QueryEmbedding::Local => {
+                let (tokenizer, session) = embedder;
+                let embedding = get_embeddings(tokenizer, session, &[query])?
+                    .pop()
+                    .context("No embedding for the query")?;
+                VectorInput::new_dense(embedding)
+            }
+        })
+    }
+
+    /// Dense search, or reciprocal rank fusion of dense and BM25 search if hybrid.
+    pub fn build_query(
+        &self,
+        query: &str,
+        dense: VectorInput,
+        conditions: Vec<Condition>,
+        limit: u64,
+    ) -> QueryPoints {
+        let builder = QueryPointsBuilder::new(SECTION_COLLECTION_NAME)
+            .filter(Filter::must(conditions))
+            .limit(limit)
+            .with_payload(true);
+        if !self.config.hybrid {
+            return builder.query(Query::new_nearest(dense)).build();
+        }
+        builder
+            .add_prefetch(
+                PrefetchQueryBuilder::default()
+                    .query(Query::new_nearest(dense))
+                    .limit(self.config.prefetch_limit),
+            )
+            .add_prefetch(
+                PrefetchQueryBuilder::default()
+                    .query(Query::new_nearest(Document::new(query, BM25_MODEL)))
+                    .using(self.config.sparse_vector.as_str())
+                    .limit(self.config.prefetch_limit),
+            )
+            .query(Query::new_fusion(Fusion::Rrf))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranker(hybrid: bool) -> SectionRanker {
+        SectionRanker {
+            config: RankingConfig {
+                embedding: QueryEmbedding::Server,
+                hybrid,
+                sparse_vector: "bm25".to_string(),
+                prefetch_limit: 20,
+                rerank_top_k: 30,
+            },
+            reranker: None,
+        }
+    }
+
+    #[test]
+    fn scores_order_items() {
+        let items = vec!["a", "b", "c", "d"];
+        assert_eq!(order_by_scores(items, &[0.1, 2.0, -1.0, 0.5], 3), vec!["b", "d", "a"]);
+    }
+
+    #[test]
+    fn hybrid_query_fuses_dense_and_sparse() {
+        let dense = VectorInput::new_dense(vec![0.1, 0.2]);
+        let query = ranker(true).build_query("hnsw", dense.clone(), vec![], 10);
+        assert_eq!(query.prefetch.len(), 2);
+        assert_eq!(query.prefetch[1].using.as_deref(), Some("bm25"));
+        assert_eq!(query.prefetch[0].limit, Some(20));
+        assert_eq!(query.limit, Some(10));
+
+        let query = ranker(false).build_query("hnsw", dense, vec![], 10);
+        assert!(query.prefetch.is_empty());
+    }
+
+    #[test]
+    fn candidates_without_reranker() {
+        assert_eq!(ranker(false).candidates(10), 10);
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+
+use pulldown_cmark_escape::{escape_href, escape_html};
+use serde::Serialize;
+
+use super::models::{Section, SectionSearchResult};
+
+/// A heading in the table of contents.
+#[derive(Debug, Serialize)]
+pub struct TocEntry {
+    pub title: String,
+    pub slug: String,
+    pub level: i64,
+    pub link: String,
+    pub children: Vec<TocEntry>,
+}
+
+/// A page in the tree of subpages.
+#[derive(Debug, Serialize)]
+pub struct PageNode {
+    pub name: String,
+    pub url: String,
+    pub children: Vec<PageNode>,
+}
+
+#[derive(Serialize)]
+struct TocView<'a> {
+    up_url: String,
+    toc: &'a [TocEntry],
+    pages: &'a [PageNode],
+    next_sections: Option<&'a str>,
+    next_sublinks: Option<&'a str>,
+}
+
+/// Nest the sections by their `parent_sections` chain, in document order.
+///
+/// The last entry of `parent_sections` is the section itself, the one before it the parent.
+/// Sections whose parent is not in the result become top level entries.
+fn build_toc(sections: &[&Section], request_path: &str) -> Vec<TocEntry> {
+    let index: HashMap<(&str, &str), usize> = sections
+        .iter()
+        .enumerate()
+        .map(|(idx, s)| ((s.page.as_str(), s.slug.as_str()), idx))
+        .collect();
+    let parents: Vec<Option<usize>> = sections
+        .iter()
+        .map(|s| {
+            let len = s.parent_sections.len();
+            let parent = s.parent_sections.get(len.checked_sub(2)?)?;
+            index.get(&(s.page.as_str(), parent.as_str())).copied()
+        })
+        .collect();
+
+    fn entry(
+        idx: usize,
+        sections: &[&Section],
+        parents: &[Option<usize>],
+        request_path: &str,
+    ) -> TocEntry {
+        let section = sections[idx];
+        TocEntry {
+            title: section.title.clone(),
+            slug: section.slug.clone(),
+            level: section.level,
+            link: SectionSearchResult::section_link(section, request_path),
+            children: (0..sections.len())
+                .filter(|child| *child != idx && parents[*child] == Some(idx))
+                .map(|child| entry(child, sections, parents, request_path))
+                .collect(),
+        }
+    }
+
+    (0..sections.len())
+        .filter(|idx| parents[*idx].is_none_or(|parent| parent == *idx))
+        .map(|idx| entry(idx, sections, &parents, request_path))
+        .collect()
+}
+
+/// Nest the sublinks (page paths) by their path segments below `page`.
+fn build_page_tree(page: &str, sublinks: &[String], base_url: &str) -> Vec<PageNode> {
+    fn insert(nodes: &mut Vec<PageNode>, prefix: &str, segments: &[&str], base_url: &str) {
+        let Some((first, rest)) = segments.split_first() else {
+            return;
+        };
+        let path = if prefix.is_empty() {
+            first.to_string()
+        } else {
+            format!("{}/{}", prefix, first)
+        };
+        let pos = match nodes.iter().position(|n| n.name == *first) {
+            Some(pos) => pos,
+            None => {
+                nodes.push(PageNode {
+                    name: first.to_string(),
+                    url: format!("{}/md/{}", base_url, path),
+                    children: vec![],
+                });
+                nodes.len() - 1
+            }
+        };
+        insert(&mut nodes[pos].children, &path, rest, base_url);
+    }
+
+    let page = page.trim_matches('/');
+    let mut sublinks: Vec<&str> = sublinks.iter().map(|s| s.trim_matches('/')).collect();
+    sublinks.sort();
+    let mut tree = vec![];
+    for sublink in sublinks {
+        let (prefix, relative) = match sublink.strip_prefix(page) {
+            Some(rest) if !page.is_empty() && rest.starts_with('/') => (page, &rest[1..]),
+            _ => ("", sublink),
+        };
+        let segments: Vec<&str> = relative.split('/').filter(|s| !s.is_empty()).collect();
+        insert(&mut tree, prefix, &segments, base_url);
+    }
+    tree
+}
+
+fn push_toc_markdown(out: &mut String, entries: &[TocEntry], depth: usize) {
+    for entry in entries {
+        out.push_str(&format!(
+            "{}- [{}]({})\n",
+            "  ".repeat(depth),
+            entry.title,
+            entry.link
+        ));
+        push_toc_markdown(out, &entry.children, depth + 1);
+    }
+}
+
+fn push_pages_markdown(out: &mut String, nodes: &[PageNode], depth: usize) {
+    for node in nodes {
+        out.push_str(&format!(
+            "{}- [{}]({})\n",
+            "  ".repeat(depth),
+            node.name,
+            node.url
+        ));
+        push_pages_markdown(out, &node.children, depth + 1);
+    }
+}
+
+fn push_link_html(out: &mut String, title: &str, link: &str) {
+    out.push_str("<a href=\"");
+    escape_href(&mut *out, link).unwrap();
+    out.push_str("\">");
+    escape_html(&mut *out, title).unwrap();
+    out.push_str("</a>");
+}
+
+fn push_toc_html(out: &mut String, entries: &[TocEntry]) {
+    out.push_str("<ul>\n");
+    for entry in entries {
+        out.push_str("<li>");
+        push_link_html(out, &entry.title, &entry.link);
+        if !entry.children.is_empty() {
+            out.push('\n');
+            push_toc_html(out, &entry.children);
+        }
+        out.push_str("</li>\n");
+    }
+    out.push_str("</ul>\n");
+}
+
+fn push_pages_html(out: &mut String, nodes: &[PageNode]) {
+    out.push_str("<ul>\n");
+    for node in nodes {
+        out.push_str("<li>");
+        push_link_html(out, &node.name, &node.url);
+        if !node.children.is_empty() {
+            out.push('\n');
+            push_pages_html(out, &node.children);
+        }
+        out.push_str("</li>\n");
+    }
+    out.push_str("</ul>\n");
+}
+
+/// Table of contents of a section search result: the heading outline and the subpage tree.
+pub struct Toc {
+    up_url: String,
+    title: String,
+    toc: Vec<TocEntry>,
+    pages: Vec<PageNode>,
+    next_sections: Option<String>,
+    next_sublinks: Option<String>,
+}
+
+impl Toc {
+    /// Build the table of contents, with the same arguments as
+    /// [`SectionSearchResult::to_markdown`].
+    pub fn new(
+        result: &SectionSearchResult,
+        request_path: &str,
+        request_query: Option<&str>,
+        base_url: &str,
+    ) -> Self {
+        let sections = result.sorted_sections();
+        let page = request_path.trim_matches('/').strip_prefix("md").unwrap_or_default();
+        let (next_sections, next_sublinks) = result.next_urls(request_path, request_query, base_url);
+        Self {
+            up_url: SectionSearchResult::up_url(&sections, request_path, request_query, base_url),
+            title: sections.first().map_or(request_path, |s| s.title.as_str()).to_string(),
+            toc: build_toc(&sections, request_path),
+            pages: result
+                .sublinks
+                .as_deref()
+                .map(|sublinks| build_page_tree(page, sublinks, base_url))
+                .unwrap_or_default(),
+            next_sections,
+            next_sublinks,
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut result = format!("Read one level up: {}\n", self.up_url);
+        if !self.toc.is_empty() {
+            result.push_str("\n## Table of Contents\n\n");
+            push_toc_markdown(&mut result, &self.toc, 0);
+        }
+        if let Some(next) = &self.next_sections {
+            result.push_str(&format!("\nNext page of sections: {}\n", next));
+        }
+        if !self.pages.is_empty() {
+            result.push_str("\n## Subsites to Search\n\n");
+            push_pages_markdown(&mut result, &self.pages, 0);
+        }
+        if let Some(next) = &self.next_sublinks {
+            result.push_str(&format!("\nNext page of subsites: {}\n", next));
+        }
+        result
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&TocView {
+            up_url: self.up_url.clone(),
+            toc: &self.toc,
+            pages: &self.pages,
+            next_sections: self.next_sections.as_deref(),
+            next_sublinks: self.next_sublinks.as_deref(),
+        })
+        .expect("Failed to serialize table of contents")
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html =
+            String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>");
+        escape_html(&mut html, &self.title).unwrap();
+        html.push_str("</title>\n</head>\n<body>\n<nav>");
+        push_link_html(&mut html, "Read one level up", &self.up_url);
+        html.push_str("</nav>\n");
+        if !self.toc.is_empty() {
+            html.push_str("<nav>\n<h2>Table of Contents</h2>\n");
+            push_toc_html(&mut html, &self.toc);
+            html.push_str("</nav>\n");
+        }
+        if let Some(next) = &self.next_sections {
+            html.push_str("<nav>");
+            push_link_html(&mut html, "Next page of sections", next);
+            html.push_str("</nav>\n");
+        }
+        if !self.pages.is_empty() {
+            html.push_str("<nav>\n<h2>Subsites to Search</h2>\n");
+            push_pages_html(&mut html, &self.pages);
+            html.push_str("</nav>\n");
+        }
+        if let Some(next) = &self.next_sublinks {
+            html.push_str("<nav>");
+            push_link_html(&mut html, "Next page of subsites", next);
+            html.push_str("</nav>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(slug: &str, line: i64, parent_sections: &[&str]) -> Section {
+        Section {
+            title: slug.to_uppercase(),
+            slug: slug.to_string(),
+            content: String::new(),
+            url: "documentation/guides".to_string(),
+            page: "documentation/guides".to_string(),
+            parent_sections: parent_sections.iter().map(|s| s.to_string()).collect(),
+            parent_pages: vec![],
+            level: parent_sections.len() as i64,
+            line,
+        }
+    }
+
+    fn result() -> SectionSearchResult {
+        SectionSearchResult {
+            sections: vec![
+                section("install", 10, &["guides", "install"]),
+                section("guides", 1, &["guides"]),
+                section("docker", 20, &["guides", "install", "docker"]),
+                section("faq", 30, &["guides", "faq"]),
+            ],
+            sublinks: Some(vec![
+                "documentation/guides/b".to_string(),
+                "documentation/guides/a/deep".to_string(),
+                "documentation/guides/a".to_string(),
+            ]),
+            omitted: vec![],
+            next: Default::default(),
+            navigation: None,
+        }
+    }
+
+    #[test]
+    fn nested_markdown_outline() {
+        let toc = Toc::new(&result(), "/md/documentation/guides", None, "http://h");
+        assert_eq!(
+            toc.to_markdown(),
+            "Read one level up: http://h/md/documentation\n\
+             \n## Table of Contents\n\n\
+             - [GUIDES](?s=guides)\n  \
+               - [INSTALL](?s=install)\n    \
+                 - [DOCKER](?s=docker)\n  \
+               - [FAQ](?s=faq)\n\
+             \n## Subsites to Search\n\n\
+             - [a](http://h/md/documentation/guides/a)\n  \
+               - [deep](http://h/md/documentation/guides/a/deep)\n\
+             - [b](http://h/md/documentation/guides/b)\n"
+        );
+    }
+
+    #[test]
+    fn orphans_are_top_level() {
+        let mut result = result();
+        result.sections.retain(|s| s.slug != "guides");
+        let toc = Toc::new(&result, "/md/documentation/guides", None, "http://h");
+        let slugs: Vec<_> = toc.toc.iter().map(|e| e.slug.as_str()).collect();
+        assert_eq!(slugs, vec!["install", "faq"]);
+        assert_eq!(toc.toc[0].children[0].slug, "docker");
+    }
+
+    #[test]
+    fn json_and_html_outline() {
+        let toc = Toc::new(&result(), "/md/documentation/guides", None, "http://h");
+        let json: serde_json::Value = serde_json::from_str(&toc.to_json()).unwrap();
+        assert_eq!(json["toc"][0]["children"][0]["link"], "?s=install");
+        assert_eq!(json["pages"][0]["children"][0]["name"], "deep");
+        let html = toc.to_html();
+        assert!(html.contains("<li><a href=\"?s=guides\">GUIDES</a>\n<ul>\n"));
+    }
+}
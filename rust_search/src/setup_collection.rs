@@ -1,31 +1,73 @@
 mod common;
+mod validation;
 
-use crate::common::{get_embedding, get_qdrant_url, COLLECTION_NAME, MODEL_PATH};
+use crate::common::{
+    get_qdrant_url, hash_bytes, mark_indexed, run_pipeline, Checkpoint, EmbedRecord,
+    PipelineConfig, QdrantSink, RejectFile, COLLECTION_NAME, MAX_TOKENS, MODEL_PATH, SITE_DATA,
+};
+use crate::validation::validate;
 use anyhow::Result;
 use ort::{Environment, SessionBuilder};
 use qdrant_client::{
     qdrant::{
-        vectors_config::Config, CreateCollection, Distance, PointId, PointStruct,
-        UpsertPointsBuilder, Value, VectorParams, Vectors, VectorsConfig,
+        vectors_config::Config, CreateCollection, Distance, PointId, Value, VectorParams,
+        VectorsConfig,
     },
     Qdrant,
 };
-use rust_tokenizers::tokenizer::BertTokenizer;
-use std::{
-    collections::HashMap,
-    fs::File,
-    io::{BufRead, BufReader, Write},
-    sync::Arc,
-};
+use rust_tokenizers::tokenizer::{BertTokenizer, Tokenizer};
+use std::{collections::HashMap, io::BufRead, sync::Arc};
 use tokio::main;
 
-const SITE_DATA: &str = "../page-search/data/abstracts.jsonl";
 const VOCAB_PATH: &str = "vocab.txt";
 const SPECIAL_TOKEN_PATH: &str = "special_tokens_map.json";
+const CHECKPOINT_PATH: &str = "setup_collection.checkpoint.json";
+const REJECT_PATH: &str = "setup_collection.rejects.jsonl";
+
+/// Validate the site data without embedding or storing anything.
+///
+/// Fails on schema violations, and with `strict` also on duplicates and over-length texts.
+fn dry_run(tokenizer: &BertTokenizer, strict: bool) -> Result<()> {
+    let site_data = std::fs::read(SITE_DATA)?;
+    let site_data = String::from_utf8_lossy(&site_data);
+    // [CLS] and [SEP] are added to every text
+    let token_count = |text: &str| tokenizer.tokenize(text).len() + 2;
+    let report = validate(site_data.lines(), token_count, MAX_TOKENS);
+    println!("{report}");
+
+    if report.violations() > 0 {
+        anyhow::bail!("{} violations found in {SITE_DATA}", report.violations());
+    }
+    if strict && report.warnings() > 0 {
+        anyhow::bail!("{} warnings found in {SITE_DATA}", report.warnings());
+    }
+    Ok(())
+}
+
+/// Parse one line of the site data into a record, using the line number as point id.
+fn parse_record(line: &str, id: u64) -> Result<EmbedRecord, String> {
+    let payload: HashMap<String, Value> =
+        serde_json::from_str(line).map_err(|err| format!("invalid JSON: {err}"))?;
+    let text = payload
+        .get("text")
+        .and_then(Value::as_str)
+        .ok_or("missing string field `text`")?
+        .to_string();
+
+    Ok(EmbedRecord {
+        id: PointId::from(id),
+        text,
+        payload,
+    })
+}
 
 #[main]
 async fn main() -> Result<()> {
-    // embed all word prefixes
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let config = PipelineConfig::from_env();
+    let args: Vec<String> = std::env::args().collect();
+    let resume = args.iter().any(|arg| arg == "--resume");
+
     let tokenizer = BertTokenizer::from_file_with_special_token_mapping(
         VOCAB_PATH,
         true,
@@ -33,33 +75,48 @@ async fn main() -> Result<()> {
         SPECIAL_TOKEN_PATH,
     )
     .unwrap();
+    if args.iter().any(|arg| arg == "--dry-run") {
+        return dry_run(&tokenizer, args.iter().any(|arg| arg == "--strict"));
+    }
     let env = Arc::new(Environment::builder().build()?);
     let session = SessionBuilder::new(&env)?.with_model_from_file(MODEL_PATH)?;
-    let id = &mut 1_u64;
-    let stdout = std::io::stdout();
-    let mut stdout = stdout.lock();
-    let site_file = File::open(SITE_DATA)?;
-    let site_reader = BufReader::new(site_file);
-    let mut points = site_reader.lines().map(move |line| {
-        let payload: HashMap<String, Value> = serde_json::from_str(&line.unwrap()).unwrap();
-        let text = payload.get("text").and_then(Value::as_str).unwrap();
 
-        let vector = get_embedding(&tokenizer, &session, text);
-
-        if (*id).is_multiple_of(100) {
-            write!(stdout, "{id}").unwrap();
-        } else {
-            write!(stdout, ".").unwrap();
-        }
-        stdout.flush().unwrap();
-        PointStruct {
-            id: Some(PointId::from(std::mem::replace(id, *id + 1))),
-            payload,
-            vectors: Some(Vectors::from(vector)),
+    let site_data = std::fs::read(SITE_DATA)?;
+    // counted over the valid records only, like the batches skipped on resume
+    let total = site_data
+        .lines()
+        .zip(1_u64..)
+        .filter(|(line, id)| {
+            line.as_ref()
+                .is_ok_and(|line| parse_record(line, *id).is_ok())
+        })
+        .count();
+    log::info!("{total} records found");
+    let mut checkpoint = Checkpoint::open(
+        CHECKPOINT_PATH,
+        COLLECTION_NAME,
+        &hash_bytes(&site_data),
+        config.upsert_batch_size,
+        resume,
+    )?;
+    let mut rejects = RejectFile::create(REJECT_PATH)?;
+    let mut reject_error = None;
+    let records = site_data.lines().zip(1_u64..).filter_map(|(line, id)| {
+        let parsed = line
+            .map_err(|err| (String::new(), err.to_string()))
+            .and_then(|line| parse_record(&line, id).map_err(|err| (line, err)));
+        match parsed {
+            Ok(record) => Some(record),
+            Err((input, err)) => {
+                if let Err(write_err) = rejects.reject(id as usize, &err, &input) {
+                    reject_error.get_or_insert(write_err);
+                }
+                None
+            }
         }
     });
 
-    // store the word prefixes with embedding
+    // store the records with embedding
     let qdrant_url = get_qdrant_url();
     let mut builder = Qdrant::from_url(&qdrant_url);
     if let Ok(key) = std::env::var("QDRANT_API_KEY") {
@@ -82,14 +139,26 @@ async fn main() -> Result<()> {
             })
             .await?;
     }
-    loop {
-        let p = (&mut points).take(1024).collect::<Vec<_>>();
-        if p.is_empty() {
-            break;
-        }
-        let request = UpsertPointsBuilder::new(COLLECTION_NAME, p);
 
-        qdrant_client.upsert_points(request).await?;
+    run_pipeline(
+        &QdrantSink {
+            client: &qdrant_client,
+            collection: COLLECTION_NAME,
+            max_retries: config.max_retries,
+        },
+        &tokenizer,
+        &session,
+        records,
+        Some(total),
+        &config,
+        Some(&mut checkpoint),
+    )
+    .await?;
+    mark_indexed(&qdrant_client, COLLECTION_NAME).await?;
+    if let Some(err) = reject_error {
+        return Err(err);
     }
+    rejects.finish()?;
+    checkpoint.remove()?;
     Ok(())
 }
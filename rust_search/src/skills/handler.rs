@@ -0,0 +1,165 @@
+use actix_web::web::{Data, Query};
+use actix_web::{get, HttpRequest, HttpResponse};
+use qdrant_client::qdrant::r#match::MatchValue;
+use qdrant_client::qdrant::{
+    Condition, Document, Filter, QueryPointsBuilder, ScoredPoint, VectorInput,
+};
+use qdrant_client::Qdrant;
+use serde::Deserialize;
+
+use crate::cache::HttpCache;
+use crate::common::{env_or, SKILLS_COLLECTION_NAME};
+use crate::sections::{parse_points, query_by_filter, slugify_heading};
+use crate::snippet::SNIPPET_ENCODER;
+
+use super::models::{Skill, SkillSearchResult};
+
+fn skills_exact_limit() -> u64 {
+    env_or("SKILLS_EXACT_LIMIT", 100)
+}
+
+fn skills_search_limit() -> u64 {
+    env_or("SKILLS_SEARCH_LIMIT", 3)
+}
+
+/// A query searches all sub-pages of `path`, otherwise the page itself is retrieved.
+fn build_conditions(path: &str, query: Option<&str>) -> Vec<Condition> {
+    if query.is_some() {
+        vec![Condition::matches(
+            "parent_pages",
+            MatchValue::Keyword(path.to_string()),
+        )]
+    } else if !path.is_empty() {
+        vec![Condition::matches(
+            "page",
+            MatchValue::Keyword(path.to_string()),
+        )]
+    } else {
+        vec![]
+    }
+}
+
+async fn query_by_document(
+    client: &Qdrant,
+    query: &str,
+    conditions: Vec<Condition>,
+) -> anyhow::Result<Vec<ScoredPoint>> {
+    let result = client
+        .query(
+            QueryPointsBuilder::new(SKILLS_COLLECTION_NAME)
+                .query(VectorInput::from(Document::new(query, SNIPPET_ENCODER)))
+                .filter(Filter::must(conditions))
+                .limit(skills_search_limit())
+                .with_payload(true),
+        )
+        .await?;
+    Ok(result.result)
+}
+
+async fn search_skills(
+    client: &Qdrant,
+    query: Option<&str>,
+    path: &str,
+) -> anyhow::Result<SkillSearchResult> {
+    let clean_path = path.trim_matches('/');
+    let conditions = build_conditions(clean_path, query);
+
+    let Some(query) = query else {
+        // everything on this page
+        let points = query_by_filter(
+            client,
+            SKILLS_COLLECTION_NAME,
+            conditions,
+            skills_exact_limit(),
+        )
+        .await?;
+        return Ok(SkillSearchResult {
+            skills: parse_points(points, Skill::from_payload),
+        });
+    };
+
+    // Try exact name match first
+    let mut exact_conditions = conditions.clone();
+    exact_conditions.push(Condition::matches(
+        "name",
+        MatchValue::Keyword(slugify_heading(query)),
+    ));
+    let points = query_by_filter(
+        client,
+        SKILLS_COLLECTION_NAME,
+        exact_conditions,
+        skills_exact_limit(),
+    )
+    .await?;
+    if !points.is_empty() {
+        return Ok(SkillSearchResult {
+            skills: parse_points(points, Skill::from_payload),
+        });
+    }
+
+    // Fallback to server-side vector search
+    let points = query_by_document(client, query, conditions).await?;
+    Ok(SkillSearchResult {
+        skills: parse_points(points, Skill::from_payload),
+    })
+}
+
+#[derive(Deserialize)]
+struct SkillSearch {
+    q: Option<String>,
+}
+
+#[get("/skills/{path:.*}")]
+pub async fn skills_handler(
+    path: actix_web::web::Path<String>,
+    req: HttpRequest,
+    query: Query<SkillSearch>,
+    qdrant: Data<Qdrant>,
+    cache: Data<HttpCache>,
+) -> HttpResponse {
+    let cache_control = &cache.config.md_cache_control;
+    let validators = cache.validators(SKILLS_COLLECTION_NAME, &req).await;
+    if let Some(validators) = validators.as_ref().filter(|v| v.is_fresh(&req)) {
+        return validators.not_modified(cache_control);
+    }
+
+    let result = search_skills(qdrant.get_ref(), query.q.as_deref(), &path.into_inner()).await;
+
+    match result {
+        Ok(skill_result) => {
+            log::info!("skills={}", skill_result.skills.len());
+            let mut response = HttpResponse::Ok();
+            if let Some(validators) = &validators {
+                validators.apply(&mut response, cache_control);
+            }
+            response
+                .content_type("text/markdown; charset=utf-8")
+                .body(skill_result.to_markdown())
+        }
+        Err(e) => {
+            log::error!("Skill search error: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(condition: &Condition) -> &str {
+        match &condition.condition_one_of {
+            Some(qdrant_client::qdrant::condition::ConditionOneOf::Field(field)) => &field.key,
+            _ => panic!("not a field condition"),
+        }
+    }
+
+    #[test]
+    fn conditions_depend_on_query() {
+        let with_query = build_conditions("skills", Some("search"));
+        assert_eq!(field(&with_query[0]), "parent_pages");
+        let page = build_conditions("skills/search", None);
+        assert_eq!(field(&page[0]), "page");
+        assert!(build_conditions("", None).is_empty());
+    }
+}
@@ -0,0 +1,4 @@
+mod handler;
+mod models;
+
+pub use handler::skills_handler;
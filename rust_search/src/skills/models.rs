@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use qdrant_client::qdrant::Value;
+use qdrant_client::Payload;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+pub struct Skill {
+    pub name: String,
+    pub description: String,
+    pub content: String,
+    pub url: String,
+    pub page: String,
+    pub parent_pages: Vec<String>,
+}
+
+impl Skill {
+    pub fn from_payload(payload: HashMap<String, Value>) -> Option<Self> {
+        Payload::from(payload).deserialize().ok()
+    }
+
+    pub fn frontmatter(&self) -> String {
+        format!(
+            "---\nname: {}\ndescription: {}\n---",
+            self.name, self.description
+        )
+    }
+}
+
+pub struct SkillSearchResult {
+    pub skills: Vec<Skill>,
+}
+
+impl SkillSearchResult {
+    /// Render the skills as markdown, mirroring the Python implementation.
+    pub fn to_markdown(&self) -> String {
+        self.skills
+            .iter()
+            .map(|skill| format!("{}\n{}", skill.frontmatter(), skill.content))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skill(name: &str) -> Skill {
+        Skill {
+            name: name.to_string(),
+            description: format!("How to {}", name),
+            content: format!("# {}\n\nSteps.", name),
+            url: format!("https://qdrant.tech/skills/{}/", name),
+            page: format!("skills/{}", name),
+            parent_pages: vec!["skills".to_string(), format!("skills/{}", name)],
+        }
+    }
+
+    #[test]
+    fn markdown_output() {
+        let result = SkillSearchResult {
+            skills: vec![skill("search"), skill("index")],
+        };
+        assert_eq!(
+            result.to_markdown(),
+            "---\nname: search\ndescription: How to search\n---\n# search\n\nSteps.\n\n\
+             ---\nname: index\ndescription: How to index\n---\n# index\n\nSteps."
+        );
+    }
+
+    #[test]
+    fn from_payload() {
+        let payload: HashMap<String, Value> = [
+            ("name", Value::from("search")),
+            ("description", Value::from("d")),
+            ("content", Value::from("c")),
+            ("url", Value::from("u")),
+            ("page", Value::from("skills/search")),
+            ("parent_pages", Value::from(vec!["skills"])),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        assert_eq!(Skill::from_payload(payload).unwrap().name, "search");
+    }
+}
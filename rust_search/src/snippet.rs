@@ -0,0 +1,195 @@
+//! Schema of the code snippets in the `snippet-search` collection.
+//!
+//! Mirrors `Snippet` of `site_search/snippets.py`. Every indexing run writes its snippets
+//! under a new `revision`, and searches only look at the latest one.
+
+// Allow unused code, as the indexer and the service use different parts of the schema
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+
+use qdrant_client::qdrant::facet_value::Variant;
+use qdrant_client::qdrant::vectors_config::Config;
+use qdrant_client::qdrant::{Condition, Document, FacetCountsBuilder, Filter, Value, VectorsConfig};
+use qdrant_client::{Payload, Qdrant};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::common::{hash_bytes, SNIPPET_COLLECTION_NAME};
+
+pub const SNIPPET_ENCODER: &str = "mixedbread-ai/mxbai-embed-large-v1";
+pub const DENSE_VECTOR: &str = "dense";
+/// Dimensions of the dense vectors written by `index_snippets` with the local ONNX model
+pub const LOCAL_DENSE_SIZE: u64 = 384;
+pub const SPARSE_VECTOR: &str = "sparse";
+pub const BM25_MODEL: &str = "qdrant/bm25";
+/// Marks the points of a revision that is still being written, see `index_snippets`
+pub const PENDING_FIELD: &str = "pending";
+/// Hash of the code of snippets with a generated description, see `snippet_description`
+pub const CODE_HASH_FIELD: &str = "code_hash";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceInfo {
+    pub url: String,
+    pub hash: String,
+    pub lines: Option<(i64, i64)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnippetContext {
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snippet {
+    pub code: String,
+    pub language: String,
+    pub version: String,
+    pub revision: i64,
+    pub package_name: String,
+    pub source: SourceInfo,
+    pub context: SnippetContext,
+    pub description: Option<String>,
+}
+
+impl Snippet {
+    pub fn from_payload(payload: HashMap<String, Value>) -> Option<Self> {
+        Payload::from(payload).deserialize().ok()
+    }
+
+    /// The text that is embedded: the description, or the code in its context.
+    pub fn document(&self) -> String {
+        match &self.description {
+            Some(description) => description.clone(),
+            None => format!(
+                "{}\n```{}\n{}\n```\n{}",
+                self.context.before, self.language, self.code, self.context.after
+            ),
+        }
+    }
+
+    /// Payload as stored in the collection, where the description is always the document.
+    pub fn payload(&self) -> anyhow::Result<HashMap<String, Value>> {
+        let mut value = serde_json::to_value(self)?;
+        value["description"] = self.document().into();
+        Ok(Payload::try_from(value)?.into())
+    }
+
+    pub fn code_hash(&self) -> String {
+        hash_bytes(self.code.as_bytes())
+    }
+
+    /// Point id derived from the snippet and its revision, so that every revision is written
+    /// next to the previous one instead of overwriting it.
+    pub fn point_id(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in [
+            &self.revision.to_string(),
+            &self.package_name,
+            &self.source.url,
+            &self.context.before,
+            &self.code,
+            &self.context.after,
+        ] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        let hex: String = hasher.finalize()[..16]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+/// BM25 document of `text`, without language specific stemming and stopwords.
+pub fn bm25_document(text: &str) -> Document {
+    let mut document = Document::new(text, BM25_MODEL);
+    document
+        .options
+        .insert("language".to_string(), "none".into());
+    document
+}
+
+/// Size of the `DENSE_VECTOR` of a collection, if it has one.
+pub fn dense_vector_size(config: &VectorsConfig) -> Option<u64> {
+    match config.config.as_ref()? {
+        Config::ParamsMap(params) => params.map.get(DENSE_VECTOR).map(|p| p.size),
+        Config::Params(_) => None,
+    }
+}
+
+/// Highest complete `revision` in the collection, or 0 if it is empty.
+pub async fn latest_revision(client: &Qdrant) -> anyhow::Result<i64> {
+    let response = client
+        .facet(
+            FacetCountsBuilder::new(SNIPPET_COLLECTION_NAME, "revision")
+                .filter(Filter::must([Condition::is_empty(PENDING_FIELD)]))
+                .limit(1_000_000),
+        )
+        .await?;
+    Ok(response
+        .hits
+        .into_iter()
+        .filter_map(|hit| match hit.value?.variant? {
+            Variant::IntegerValue(revision) => Some(revision),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_roundtrip() {
+        let snippet = Snippet {
+            code: "client.search()".to_string(),
+            language: "python".to_string(),
+            version: "1.2.0".to_string(),
+            revision: 3,
+            package_name: "qdrant-client".to_string(),
+            source: SourceInfo {
+                url: "https://qdrant.tech/documentation/".to_string(),
+                hash: "abc".to_string(),
+                lines: Some((10, 12)),
+            },
+            context: SnippetContext {
+                before: "Search:".to_string(),
+                after: String::new(),
+            },
+            description: None,
+        };
+        let payload = Payload::try_from(serde_json::to_value(&snippet).unwrap()).unwrap();
+        let parsed = Snippet::from_payload(payload.into()).unwrap();
+        assert_eq!(parsed, snippet);
+        assert_eq!(
+            parsed.document(),
+            "Search:\n```python\nclient.search()\n```\n"
+        );
+    }
+
+    #[test]
+    fn dense_size_of_named_vectors() {
+        use qdrant_client::qdrant::{Distance, VectorParamsBuilder, VectorsConfigBuilder};
+
+        let mut config = VectorsConfigBuilder::default();
+        config.add_named_vector_params(
+            DENSE_VECTOR,
+            VectorParamsBuilder::new(1024, Distance::Cosine),
+        );
+        assert_eq!(dense_vector_size(&VectorsConfig::from(config)), Some(1024));
+        let unnamed = VectorsConfig::from(VectorParamsBuilder::new(384, Distance::Cosine));
+        assert_eq!(dense_vector_size(&unnamed), None);
+    }
+}
@@ -0,0 +1,519 @@
+//! Generated descriptions of code snippets, which become the embedded `document`.
+//!
+//! `SNIPPETS_DESCRIPTIONS` selects the generator:
+//! - `none` – no descriptions, snippets are embedded with their context (default)
+//! - `openai` – an OpenAI-compatible chat completions endpoint at `SNIPPETS_DESCRIPTION_URL`
+//! - `cached` – reuse the descriptions of unchanged code from the live revision, and generate
+//!   the missing ones with the endpoint if `SNIPPETS_DESCRIPTION_URL` is set
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Context;
+use futures::StreamExt;
+use qdrant_client::qdrant::{
+    Condition, Filter, PayloadIncludeSelector, PointId, ScrollPointsBuilder,
+};
+use qdrant_client::Qdrant;
+
+use crate::common::{env_or, Progress, SNIPPET_COLLECTION_NAME};
+use crate::snippet::{Snippet, CODE_HASH_FIELD};
+
+const SCROLL_LIMIT: u32 = 1000;
+
+const PROMPT: &str = "
+You are creating a searchable description for a code snippet. The description will be used for vector search by both humans and AI agents.
+
+Context before the snippet:
+```
+{context_before}
+```
+
+Code snippet:
+```
+{code}
+```
+
+Context after the snippet:
+```
+{context_after}
+```
+
+Write a concise, keyword-rich description (2-4 sentences) that includes:
+1. What the code does (primary functionality and purpose)
+2. Key technical concepts, methods, classes, or libraries used
+3. The use case or problem it solves
+4. Any important parameters, return values, or side effects
+
+Focus on searchable terms that developers would use when looking for this functionality. Be specific and technical.
+";
+
+/// Which description generator the indexer uses (`SNIPPETS_DESCRIPTIONS`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptionMode {
+    None,
+    OpenAi,
+    Cached,
+}
+
+impl DescriptionMode {
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("SNIPPETS_DESCRIPTIONS").as_deref() {
+            Err(_) | Ok("none") => Ok(Self::None),
+            Ok("openai") => Ok(Self::OpenAi),
+            Ok("cached") => Ok(Self::Cached),
+            Ok(other) => anyhow::bail!("Unknown snippet description mode `{other}`"),
+        }
+    }
+}
+
+/// Writes the description of a snippet.
+pub trait DescriptionGenerator: Sync {
+    /// Description of `snippet`, or `None` to embed the snippet with its context.
+    fn describe(
+        &self,
+        snippet: &Snippet,
+    ) -> impl std::future::Future<Output = anyhow::Result<Option<String>>> + Send;
+}
+
+/// Leaves the snippets without description.
+pub struct NoDescriptions;
+
+impl DescriptionGenerator for NoDescriptions {
+    async fn describe(&self, _snippet: &Snippet) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Asks an OpenAI-compatible chat completions endpoint, e.g. OpenAI or a local llama.cpp server.
+pub struct ChatCompletions {
+    client: reqwest::Client,
+    /// Full URL of the endpoint (`SNIPPETS_DESCRIPTION_URL`)
+    url: String,
+    /// `SNIPPETS_DESCRIPTION_MODEL`
+    model: String,
+    /// Sent as bearer token if set (`OPENAI_API_KEY`)
+    api_key: Option<String>,
+}
+
+impl ChatCompletions {
+    pub fn new(
+        url: String,
+        model: String,
+        api_key: Option<String>,
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            url,
+            model,
+            api_key,
+        })
+    }
+
+    /// The endpoint configured by the environment, if `SNIPPETS_DESCRIPTION_URL` is set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(url) = std::env::var("SNIPPETS_DESCRIPTION_URL") else {
+            return Ok(None);
+        };
+        Self::new(
+            url,
+            env_or("SNIPPETS_DESCRIPTION_MODEL", "gpt-5-nano".to_string()),
+            std::env::var("OPENAI_API_KEY").ok(),
+            Duration::from_secs(env_or("SNIPPETS_DESCRIPTION_TIMEOUT_SECS", 60)),
+        )
+        .map(Some)
+    }
+}
+
+fn prompt(snippet: &Snippet) -> String {
+    PROMPT
+        .replace("{context_before}", &snippet.context.before)
+        .replace("{context_after}", &snippet.context.after)
+        .replace("{code}", &snippet.code)
+}
+
+fn request_body(model: &str, snippet: &Snippet) -> serde_json::Value {
+    serde_json::json!({
+        "model": model,
+        "messages": [{"role": "user", "content": prompt(snippet)}],
+    })
+}
+
+/// Text of the first choice of a chat completions response.
+fn parse_response(body: &[u8]) -> anyhow::Result<String> {
+    let response: serde_json::Value = serde_json::from_slice(body)?;
+    let content = response["choices"][0]["message"]["content"]
+        .as_str()
+        .context("No message content in the completion")?;
+    Ok(content.trim().to_string())
+}
+
+impl DescriptionGenerator for ChatCompletions {
+    async fn describe(&self, snippet: &Snippet) -> anyhow::Result<Option<String>> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&request_body(&self.model, snippet))?);
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        if !status.is_success() {
+            anyhow::bail!(
+                "Description request for {} failed with {status}: {}",
+                snippet.source.url,
+                String::from_utf8_lossy(&body)
+            );
+        }
+        parse_response(&body).map(Some)
+    }
+}
+
+/// Reuses the descriptions of code that didn't change since an earlier revision.
+pub struct CachedDescriptions<G> {
+    /// Descriptions by the hash of their code
+    descriptions: HashMap<String, String>,
+    /// Describes the snippets that are not cached
+    inner: G,
+}
+
+impl<G: DescriptionGenerator> CachedDescriptions<G> {
+    pub fn new(descriptions: HashMap<String, String>, inner: G) -> Self {
+        Self {
+            descriptions,
+            inner,
+        }
+    }
+
+    /// Cache the generated descriptions of `revision`.
+    pub async fn load(client: &Qdrant, revision: i64, inner: G) -> anyhow::Result<Self> {
+        let mut descriptions = HashMap::new();
+        let mut offset = None;
+        loop {
+            let mut request = ScrollPointsBuilder::new(SNIPPET_COLLECTION_NAME)
+                .filter(Filter {
+                    must: vec![Condition::matches("revision", revision)],
+                    must_not: vec![Condition::is_empty(CODE_HASH_FIELD)],
+                    ..Default::default()
+                })
+                .limit(SCROLL_LIMIT)
+                .with_payload(PayloadIncludeSelector::new(vec![
+                    CODE_HASH_FIELD.to_string(),
+                    "description".to_string(),
+                ]));
+            if let Some(offset) = offset.take() {
+                request = request.offset::<PointId>(offset);
+            }
+            let response = client.scroll(request).await?;
+            for point in response.result {
+                let field = |name: &str| point.payload.get(name).and_then(|v| v.as_str()).cloned();
+                if let (Some(hash), Some(description)) =
+                    (field(CODE_HASH_FIELD), field("description"))
+                {
+                    descriptions.insert(hash, description);
+                }
+            }
+            match response.next_page_offset {
+                Some(next) => offset = Some(next),
+                None => break,
+            }
+        }
+        Ok(Self::new(descriptions, inner))
+    }
+
+    pub fn len(&self) -> usize {
+        self.descriptions.len()
+    }
+}
+
+impl<G: DescriptionGenerator> DescriptionGenerator for CachedDescriptions<G> {
+    async fn describe(&self, snippet: &Snippet) -> anyhow::Result<Option<String>> {
+        match self.descriptions.get(&snippet.code_hash()) {
+            Some(description) => Ok(Some(description.clone())),
+            None => self.inner.describe(snippet).await,
+        }
+    }
+}
+
+/// An optional generator, which leaves the snippets without description if absent.
+impl<G: DescriptionGenerator> DescriptionGenerator for Option<G> {
+    async fn describe(&self, snippet: &Snippet) -> anyhow::Result<Option<String>> {
+        match self {
+            Some(generator) => generator.describe(snippet).await,
+            None => Ok(None),
+        }
+    }
+}
+
+/// Description of `snippet`, retrying failed requests with exponential backoff.
+///
+/// A snippet whose description fails `max_retries` times is left without description, so
+/// that it is embedded with its context and the descriptions of the others are kept.
+async fn describe_with_retry(
+    generator: &impl DescriptionGenerator,
+    snippet: &Snippet,
+    max_retries: u32,
+) -> Option<String> {
+    let mut backoff = Duration::from_millis(500);
+    let mut attempt = 0;
+    loop {
+        match generator.describe(snippet).await {
+            Ok(description) => return description,
+            Err(err) if attempt < max_retries => {
+                attempt += 1;
+                log::warn!(
+                    "Description of a snippet of {} failed (attempt {attempt}/{max_retries}): {err}, retrying in {backoff:?}",
+                    snippet.source.url
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(30));
+            }
+            Err(err) => {
+                log::error!(
+                    "Description of a snippet of {} failed after {max_retries} retries, embedding its context: {err}",
+                    snippet.source.url
+                );
+                return None;
+            }
+        }
+    }
+}
+
+/// Describe all `snippets`, with at most `concurrency` descriptions in progress.
+///
+/// Returns the number of snippets that got a description.
+pub async fn describe_all(
+    generator: &impl DescriptionGenerator,
+    snippets: &mut [Snippet],
+    concurrency: usize,
+    max_retries: u32,
+    report_interval: Duration,
+) -> usize {
+    let mut progress = Progress::new("descriptions", Some(snippets.len()), report_interval);
+    let descriptions: Vec<Option<String>> = futures::stream::iter(snippets.iter())
+        .map(|snippet| describe_with_retry(generator, snippet, max_retries))
+        .buffered(concurrency.max(1))
+        .inspect(|_| progress.advance(1))
+        .collect()
+        .await;
+    progress.finish();
+    let mut described = 0;
+    for (snippet, description) in snippets.iter_mut().zip(descriptions) {
+        described += usize::from(description.is_some());
+        snippet.description = description;
+    }
+    described
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snippet::{SnippetContext, SourceInfo};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn snippet(code: &str) -> Snippet {
+        Snippet {
+            code: code.to_string(),
+            language: "python".to_string(),
+            version: "latest".to_string(),
+            revision: 2,
+            package_name: "qdrant-client".to_string(),
+            source: SourceInfo {
+                url: "https://qdrant.tech/documentation/".to_string(),
+                hash: "abc".to_string(),
+                lines: None,
+            },
+            context: SnippetContext {
+                before: "Create a client:".to_string(),
+                after: String::new(),
+            },
+            description: None,
+        }
+    }
+
+    /// Describes every snippet by its code and counts the calls.
+    #[derive(Default)]
+    struct Echo {
+        calls: AtomicUsize,
+    }
+
+    impl DescriptionGenerator for Echo {
+        async fn describe(&self, snippet: &Snippet) -> anyhow::Result<Option<String>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(format!("Runs {}", snippet.code)))
+        }
+    }
+
+    #[test]
+    fn prompt_contains_snippet() {
+        let body = request_body("local", &snippet("client.search()"));
+        assert_eq!(body["model"], "local");
+        let content = body["messages"][0]["content"].as_str().unwrap();
+        assert!(content.contains("```\nCreate a client:\n```"));
+        assert!(content.contains("```\nclient.search()\n```"));
+    }
+
+    #[test]
+    fn completion_content() {
+        let body =
+            br#"{"choices":[{"message":{"role":"assistant","content":" Searches points. "}}]}"#;
+        assert_eq!(parse_response(body).unwrap(), "Searches points.");
+        assert!(parse_response(br#"{"choices":[]}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn cache_is_used_for_unchanged_code() {
+        let cached = snippet("client.search()");
+        let descriptions = HashMap::from([(cached.code_hash(), "Cached.".to_string())]);
+        let generator = CachedDescriptions::new(descriptions, Echo::default());
+        let mut snippets = vec![cached, snippet("client.scroll()")];
+
+        let described =
+            describe_all(&generator, &mut snippets, 4, 0, Duration::from_secs(60)).await;
+        assert_eq!(described, 2);
+        assert_eq!(snippets[0].description.as_deref(), Some("Cached."));
+        assert_eq!(
+            snippets[1].description.as_deref(),
+            Some("Runs client.scroll()")
+        );
+        assert_eq!(generator.inner.calls.load(Ordering::SeqCst), 1);
+    }
+
+    /// Fails the first `failures` calls for every snippet.
+    struct Flaky {
+        failures: usize,
+        calls: std::sync::Mutex<HashMap<String, usize>>,
+    }
+
+    impl DescriptionGenerator for Flaky {
+        async fn describe(&self, snippet: &Snippet) -> anyhow::Result<Option<String>> {
+            let mut calls = self.calls.lock().unwrap();
+            let count = calls.entry(snippet.code.clone()).or_default();
+            *count += 1;
+            if *count <= self.failures {
+                anyhow::bail!("Timeout");
+            }
+            Ok(Some(format!("Runs {}", snippet.code)))
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_descriptions_are_retried() {
+        let generator = Flaky {
+            failures: 1,
+            calls: Default::default(),
+        };
+        let mut snippets = vec![snippet("client.search()"), snippet("client.scroll()")];
+        let described =
+            describe_all(&generator, &mut snippets, 2, 1, Duration::from_secs(60)).await;
+        assert_eq!(described, 2);
+        assert_eq!(
+            snippets[1].description.as_deref(),
+            Some("Runs client.scroll()")
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_descriptions_fall_back_to_context() {
+        let generator = Flaky {
+            failures: 2,
+            calls: Default::default(),
+        };
+        let mut snippets = vec![snippet("client.search()")];
+        let described =
+            describe_all(&generator, &mut snippets, 1, 1, Duration::from_secs(60)).await;
+        assert_eq!(described, 0);
+        assert!(snippets[0].description.is_none());
+        assert_eq!(generator.calls.lock().unwrap()["client.search()"], 2);
+    }
+
+    #[tokio::test]
+    async fn no_descriptions() {
+        let mut snippets = vec![snippet("client.search()")];
+        let described = describe_all(
+            &NoDescriptions,
+            &mut snippets,
+            1,
+            0,
+            Duration::from_secs(60),
+        )
+        .await;
+        assert_eq!(described, 0);
+        assert!(snippets[0].description.is_none());
+    }
+
+    /// End of the body of a request whose headers were read, by its `content-length`.
+    fn content_end(request: &[u8]) -> Option<usize> {
+        let headers_end = request.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+        let headers = String::from_utf8_lossy(&request[..headers_end]).to_lowercase();
+        let length = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map_or(0, |length| length.trim().parse().unwrap());
+        Some(headers_end + length)
+    }
+
+    /// Serve one chat completion on a local port, returning the URL and the received request.
+    fn stub_endpoint(reply: &'static str) -> (String, std::thread::JoinHandle<String>) {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/v1/chat/completions",
+            listener.local_addr().unwrap()
+        );
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 4096];
+            // read the headers, then as much of the body as they announce
+            let mut body_end = None;
+            while body_end.is_none_or(|end| request.len() < end) {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+                if body_end.is_none() {
+                    body_end = content_end(&request);
+                }
+            }
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                reply.len(),
+                reply
+            )
+            .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn openai_compatible_endpoint() {
+        let (url, handle) = stub_endpoint(r#"{"choices":[{"message":{"content":"Searches."}}]}"#);
+        let generator = ChatCompletions::new(
+            url,
+            "local".to_string(),
+            Some("secret".to_string()),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        let description = generator
+            .describe(&snippet("client.search()"))
+            .await
+            .unwrap();
+        assert_eq!(description.as_deref(), Some("Searches."));
+
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /v1/chat/completions "));
+        assert!(request
+            .to_lowercase()
+            .contains("authorization: bearer secret"));
+        assert!(request.contains(r#""model":"local""#));
+    }
+}
@@ -0,0 +1,292 @@
+//! Extraction of code snippets from local markdown files.
+//!
+//! Mirrors `_extract_from_markdown_tree` of `site_search/snippets.py`: every top-level fenced
+//! code block becomes a snippet, with up to `CONTEXT_LINES` lines of the surrounding
+//! non-code blocks as context.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+
+use crate::common::hash_bytes;
+use crate::snippet::{Snippet, SnippetContext, SourceInfo};
+
+const CONTEXT_LINES: usize = 10;
+
+/// Package and version the snippets are attributed to.
+#[derive(Debug, Clone)]
+pub struct SnippetOrigin {
+    pub package_name: String,
+    pub version: String,
+}
+
+pub fn normalize_language(language: &str) -> String {
+    let language = language.trim().to_lowercase();
+    match language.as_str() {
+        "bash" | "console" | "env" | "sh" => "shell",
+        "py" => "python",
+        "jsx" | "js" => "javascript",
+        "http request" => "http",
+        "txt" => "text",
+        _ => return language,
+    }
+    .to_string()
+}
+
+#[derive(Debug, PartialEq)]
+enum BlockKind {
+    Fence {
+        info: String,
+        code: String,
+    },
+    /// Indented code block, which is skipped like fences when looking for context
+    Indented,
+    Other,
+}
+
+/// A top-level block of a markdown document.
+#[derive(Debug)]
+struct Block {
+    kind: BlockKind,
+    /// Lines of the block, 0-based and end exclusive
+    lines: Range<usize>,
+}
+
+impl Block {
+    fn is_code(&self) -> bool {
+        self.kind != BlockKind::Other
+    }
+}
+
+/// Split `content` into its top-level blocks.
+fn top_level_blocks(content: &str) -> Vec<Block> {
+    let newlines: Vec<usize> = content.match_indices('\n').map(|(i, _)| i).collect();
+    let line_of = |offset: usize| newlines.partition_point(|&i| i < offset);
+    let lines = |range: &Range<usize>| {
+        line_of(range.start)..line_of(range.end.saturating_sub(1).max(range.start)) + 1
+    };
+
+    let mut blocks: Vec<Block> = vec![];
+    let mut depth = 0_usize;
+    for (event, range) in Parser::new(content).into_offset_iter() {
+        match event {
+            Event::Start(tag) => {
+                if depth == 0 {
+                    let kind = match tag {
+                        Tag::CodeBlock(CodeBlockKind::Fenced(info)) => BlockKind::Fence {
+                            info: info.to_string(),
+                            code: String::new(),
+                        },
+                        Tag::CodeBlock(CodeBlockKind::Indented) => BlockKind::Indented,
+                        _ => BlockKind::Other,
+                    };
+                    blocks.push(Block {
+                        kind,
+                        lines: lines(&range),
+                    });
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth = depth.saturating_sub(1),
+            Event::Text(text) if depth == 1 => {
+                if let Some(Block {
+                    kind: BlockKind::Fence { code, .. },
+                    ..
+                }) = blocks.last_mut()
+                {
+                    code.push_str(&text);
+                }
+            }
+            // leaf blocks without start and end, e.g. thematic breaks
+            _ if depth == 0 => blocks.push(Block {
+                kind: BlockKind::Other,
+                lines: lines(&range),
+            }),
+            _ => {}
+        }
+    }
+    blocks
+}
+
+/// Context of the block at `idx`: the lines before and after it, skipping adjacent code blocks.
+fn format_context(blocks: &[Block], idx: usize, lines: &[&str]) -> SnippetContext {
+    let block = &blocks[idx];
+    let mut start = block.lines.start + 1;
+    let mut end = block.lines.end.saturating_sub(1);
+    if let Some(prev) = blocks[..idx].iter().rev().find(|b| !b.is_code()) {
+        start = prev.lines.end;
+    }
+    if let Some(next) = blocks[idx + 1..].iter().find(|b| !b.is_code()) {
+        end = next.lines.start;
+    }
+    let window = |range: Range<usize>| {
+        let range = range.start.min(lines.len())..range.end.min(lines.len());
+        lines[range].join("\n")
+    };
+    SnippetContext {
+        before: window(start.saturating_sub(CONTEXT_LINES)..start),
+        after: window(end..end + CONTEXT_LINES),
+    }
+}
+
+/// Snippets of the markdown `content` found at `url`.
+pub fn extract_snippets(
+    content: &str,
+    url: &str,
+    origin: &SnippetOrigin,
+    revision: i64,
+) -> Vec<Snippet> {
+    let hash = hash_bytes(content.as_bytes());
+    let lines: Vec<&str> = content.split('\n').collect();
+    let blocks = top_level_blocks(content);
+    blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, block)| {
+            let BlockKind::Fence { info, code } = &block.kind else {
+                return None;
+            };
+            Some(Snippet {
+                code: code.clone(),
+                language: normalize_language(info),
+                version: origin.version.clone(),
+                revision,
+                package_name: origin.package_name.clone(),
+                source: SourceInfo {
+                    url: url.to_string(),
+                    hash: hash.clone(),
+                    lines: Some((block.lines.start as i64, block.lines.end as i64)),
+                },
+                context: format_context(&blocks, idx, &lines),
+                description: None,
+            })
+        })
+        .collect()
+}
+
+/// All markdown files below `dir`, sorted.
+pub fn markdown_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "md") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// URL of the page rendered from the markdown file at `path` below `root`.
+///
+/// `index.md` and `_index.md` are the page of their directory.
+pub fn page_url(base_url: &str, root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path).with_extension("");
+    let mut segments: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if segments
+        .last()
+        .is_some_and(|last| last == "index" || last == "_index")
+    {
+        segments.pop();
+    }
+    let base_url = base_url.trim_end_matches('/');
+    if segments.is_empty() {
+        format!("{}/", base_url)
+    } else {
+        format!("{}/{}/", base_url, segments.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origin() -> SnippetOrigin {
+        SnippetOrigin {
+            package_name: "qdrant-client".to_string(),
+            version: "latest".to_string(),
+        }
+    }
+
+    const PAGE: &str = "# Search\n\
+                        \n\
+                        Create a client:\n\
+                        \n\
+                        ```Python\n\
+                        client = QdrantClient()\n\
+                        ```\n\
+                        \n\
+                        ```bash\n\
+                        pip install qdrant-client\n\
+                        ```\n\
+                        \n\
+                        Then search.\n";
+
+    #[test]
+    fn languages_are_normalized() {
+        assert_eq!(normalize_language(" Bash "), "shell");
+        assert_eq!(normalize_language("HTTP request"), "http");
+        assert_eq!(normalize_language("rust"), "rust");
+    }
+
+    #[test]
+    fn fences_with_context() {
+        let snippets = extract_snippets(PAGE, "https://qdrant.tech/search/", &origin(), 4);
+        assert_eq!(snippets.len(), 2);
+
+        let python = &snippets[0];
+        assert_eq!(python.code, "client = QdrantClient()\n");
+        assert_eq!(python.language, "python");
+        assert_eq!(python.revision, 4);
+        assert_eq!(python.source.lines, Some((4, 7)));
+        assert_eq!(python.source.hash, hash_bytes(PAGE.as_bytes()));
+        assert_eq!(python.context.before, "# Search\n\nCreate a client:");
+        // the following code block is skipped
+        assert_eq!(python.context.after, "Then search.\n");
+
+        let shell = &snippets[1];
+        assert_eq!(shell.language, "shell");
+        assert_eq!(shell.source.lines, Some((8, 11)));
+        assert_eq!(shell.context.before, python.context.before);
+    }
+
+    #[test]
+    fn nested_fences_are_ignored() {
+        let content = "- item\n\n  ```python\n  nested()\n  ```\n";
+        assert!(extract_snippets(content, "u", &origin(), 1).is_empty());
+    }
+
+    #[test]
+    fn urls_of_pages() {
+        let root = Path::new("content");
+        assert_eq!(
+            page_url(
+                "https://qdrant.tech/",
+                root,
+                Path::new("content/documentation/_index.md")
+            ),
+            "https://qdrant.tech/documentation/"
+        );
+        assert_eq!(
+            page_url(
+                "https://qdrant.tech",
+                root,
+                Path::new("content/documentation/search.md")
+            ),
+            "https://qdrant.tech/documentation/search/"
+        );
+        assert_eq!(
+            page_url("https://qdrant.tech/", root, Path::new("content/index.md")),
+            "https://qdrant.tech/"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::http::header::{ACCEPT, VARY};
+use actix_web::web::{Data, Query};
+use actix_web::{get, HttpRequest, HttpResponse};
+use anyhow::Context;
+use ort::Session;
+use qdrant_client::qdrant::{
+    Condition, Document, Filter, PrefetchQueryBuilder, Query as QdrantQuery, QueryPoints,
+    QueryPointsBuilder, RrfBuilder, VectorInput,
+};
+use qdrant_client::Qdrant;
+use rust_tokenizers::tokenizer::BertTokenizer;
+use serde::Deserialize;
+
+use crate::common::{env_or, get_embeddings, SNIPPET_COLLECTION_NAME};
+use crate::format::{resolve_format, ResponseFormat};
+use crate::snippet::{
+    bm25_document, latest_revision, Snippet, DENSE_VECTOR, SNIPPET_ENCODER, SPARSE_VECTOR,
+};
+
+use super::models::SnippetSearchResult;
+
+const SNIPPET_FORMATS: &[ResponseFormat] = &[ResponseFormat::Json, ResponseFormat::Markdown];
+const PREFETCH_LIMIT: u64 = 20;
+
+/// The latest revision of the snippets, faceted at most once per refresh interval.
+pub struct RevisionCache {
+    /// `SNIPPETS_REVISION_REFRESH_SECS`
+    refresh: Duration,
+    latest: Mutex<Option<(i64, Instant)>>,
+}
+
+impl RevisionCache {
+    pub fn from_env() -> Self {
+        Self {
+            refresh: Duration::from_secs(env_or("SNIPPETS_REVISION_REFRESH_SECS", 60)),
+            latest: Mutex::new(None),
+        }
+    }
+
+    pub async fn get(&self, client: &Qdrant) -> anyhow::Result<i64> {
+        if let Some((revision, checked_at)) = *self.latest.lock().unwrap() {
+            if checked_at.elapsed() < self.refresh {
+                return Ok(revision);
+            }
+        }
+        let revision = latest_revision(client).await?;
+        *self.latest.lock().unwrap() = Some((revision, Instant::now()));
+        Ok(revision)
+    }
+}
+
+/// Whether queries are embedded with the service's ONNX model (`SNIPPETS_EMBEDDING=local`),
+/// as needed for collections written by `index_snippets`, instead of by Qdrant.
+pub fn snippets_local_embedding() -> bool {
+    std::env::var("SNIPPETS_EMBEDDING").is_ok_and(|v| v == "local")
+}
+
+fn dense_input(query: &str, embedder: (&BertTokenizer, &Session)) -> anyhow::Result<VectorInput> {
+    if !snippets_local_embedding() {
+        return Ok(VectorInput::from(Document::new(query, SNIPPET_ENCODER)));
+    }
+    let (tokenizer, session) = embedder;
+    let embedding = get_embeddings(tokenizer, session, &[query])?
+        .pop()
+        .context("No embedding for the query")?;
+    Ok(VectorInput::new_dense(embedding))
+}
+
+/// Reciprocal rank fusion of dense and BM25 search within one language and revision.
+fn build_query(
+    query: &str,
+    dense: VectorInput,
+    language: &str,
+    revision: i64,
+    limit: u64,
+) -> QueryPoints {
+    QueryPointsBuilder::new(SNIPPET_COLLECTION_NAME)
+        .add_prefetch(
+            PrefetchQueryBuilder::default()
+                .query(QdrantQuery::new_nearest(dense))
+                .using(DENSE_VECTOR)
+                .limit(PREFETCH_LIMIT),
+        )
+        .add_prefetch(
+            PrefetchQueryBuilder::default()
+                .query(QdrantQuery::new_nearest(bm25_document(query)))
+                .using(SPARSE_VECTOR)
+                .limit(PREFETCH_LIMIT),
+        )
+        .query(QdrantQuery::new_rrf(RrfBuilder::with_k(1)))
+        .filter(Filter::must([
+            Condition::matches("language", language.to_string()),
+            Condition::matches("revision", revision),
+        ]))
+        .limit(limit)
+        .with_payload(true)
+        .build()
+}
+
+async fn search_snippets(
+    client: &Qdrant,
+    revisions: &RevisionCache,
+    embedder: (&BertTokenizer, &Session),
+    query: &str,
+    language: &str,
+    limit: u64,
+) -> anyhow::Result<SnippetSearchResult> {
+    let revision = revisions.get(client).await?;
+    let dense = dense_input(query, embedder)?;
+    let result = client
+        .query(build_query(query, dense, language, revision, limit))
+        .await?;
+    Ok(SnippetSearchResult {
+        snippets: result
+            .result
+            .into_iter()
+            .filter_map(|p| Snippet::from_payload(p.payload))
+            .collect(),
+    })
+}
+
+#[derive(Deserialize)]
+struct SnippetSearch {
+    language: String,
+    query: String,
+    limit: Option<u64>,
+    format: Option<String>,
+}
+
+#[get("/snippets/search")]
+pub async fn snippets_handler(
+    req: HttpRequest,
+    query: Query<SnippetSearch>,
+    qdrant: Data<Qdrant>,
+    revisions: Data<RevisionCache>,
+    context: Data<(BertTokenizer, Session, Qdrant)>,
+) -> HttpResponse {
+    let limit = query.limit.unwrap_or(3);
+    let (tokenizer, session, _) = context.get_ref();
+    let result = search_snippets(
+        qdrant.get_ref(),
+        revisions.get_ref(),
+        (tokenizer, session),
+        &query.query,
+        &query.language,
+        limit,
+    )
+    .await;
+
+    match result {
+        Ok(snippet_result) => {
+            log::info!(
+                "language={} snippets={}",
+                query.language,
+                snippet_result.snippets.len()
+            );
+            let accept = req.headers().get(ACCEPT).and_then(|v| v.to_str().ok());
+            let format = resolve_format(query.format.as_deref(), accept, SNIPPET_FORMATS);
+            let body = match format {
+                ResponseFormat::Json => snippet_result.to_json(),
+                _ => snippet_result.to_markdown(),
+            };
+            HttpResponse::Ok()
+                .content_type(format.content_type())
+                .insert_header((VARY, "Accept"))
+                .body(body)
+        }
+        Err(e) => {
+            log::error!("Snippet search error: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_fuses_dense_and_bm25() {
+        let dense = VectorInput::new_dense(vec![0.1, 0.2]);
+        let query = build_query("create collection", dense, "python", 7, 3);
+        assert_eq!(query.collection_name, SNIPPET_COLLECTION_NAME);
+        assert_eq!(query.prefetch.len(), 2);
+        assert_eq!(query.prefetch[0].using.as_deref(), Some(DENSE_VECTOR));
+        assert_eq!(query.prefetch[1].using.as_deref(), Some(SPARSE_VECTOR));
+        assert_eq!(query.prefetch[1].limit, Some(PREFETCH_LIMIT));
+        assert_eq!(query.filter.unwrap().must.len(), 2);
+        assert_eq!(query.limit, Some(3));
+    }
+}
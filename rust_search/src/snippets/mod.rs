@@ -0,0 +1,4 @@
+mod handler;
+mod models;
+
+pub use handler::{snippets_handler, snippets_local_embedding, RevisionCache};
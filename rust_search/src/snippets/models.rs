@@ -0,0 +1,101 @@
+use serde::Serialize;
+
+use crate::snippet::Snippet;
+
+pub struct SnippetSearchResult {
+    pub snippets: Vec<Snippet>,
+}
+
+#[derive(Serialize)]
+struct SnippetSearchView<'a> {
+    result: &'a [Snippet],
+}
+
+impl SnippetSearchResult {
+    /// Render the snippets as markdown code blocks with their source, mirroring
+    /// `_snippets_to_markdown` of the Python implementation.
+    pub fn to_markdown(&self) -> String {
+        let mut blocks = vec![];
+        for (i, snippet) in self.snippets.iter().enumerate() {
+            let language = if snippet.language.is_empty() {
+                "text"
+            } else {
+                &snippet.language
+            };
+            blocks.push(format!("## Snippet {}\n", i + 1));
+            blocks.push(format!(
+                "*{}* (v{}) — {}\n",
+                snippet.package_name, snippet.version, snippet.source.url
+            ));
+            if let Some(description) = snippet.description.as_deref().filter(|d| !d.is_empty()) {
+                blocks.push(format!("{}\n", description));
+            }
+            blocks.push(format!("```{}\n{}\n```\n", language, snippet.code));
+        }
+        if blocks.is_empty() {
+            "No snippets found.".to_string()
+        } else {
+            blocks.join("\n")
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&SnippetSearchView {
+            result: &self.snippets,
+        })
+        .expect("Failed to serialize snippets")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snippet::{SnippetContext, SourceInfo};
+
+    fn snippet(language: &str, description: Option<&str>) -> Snippet {
+        Snippet {
+            code: "client.search()".to_string(),
+            language: language.to_string(),
+            version: "1.2.0".to_string(),
+            revision: 1,
+            package_name: "qdrant-client".to_string(),
+            source: SourceInfo {
+                url: "https://qdrant.tech/documentation/".to_string(),
+                hash: "abc".to_string(),
+                lines: None,
+            },
+            context: SnippetContext {
+                before: String::new(),
+                after: String::new(),
+            },
+            description: description.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn markdown_output() {
+        let result = SnippetSearchResult {
+            snippets: vec![
+                snippet("python", Some("Searches points.")),
+                snippet("", None),
+            ],
+        };
+        assert_eq!(
+            result.to_markdown(),
+            "## Snippet 1\n\n\
+             *qdrant-client* (v1.2.0) — https://qdrant.tech/documentation/\n\n\
+             Searches points.\n\n\
+             ```python\nclient.search()\n```\n\n\
+             ## Snippet 2\n\n\
+             *qdrant-client* (v1.2.0) — https://qdrant.tech/documentation/\n\n\
+             ```text\nclient.search()\n```\n"
+        );
+    }
+
+    #[test]
+    fn empty_result() {
+        let result = SnippetSearchResult { snippets: vec![] };
+        assert_eq!(result.to_markdown(), "No snippets found.");
+        assert_eq!(result.to_json(), r#"{"result":[]}"#);
+    }
+}
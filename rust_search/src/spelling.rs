@@ -0,0 +1,278 @@
+//! Spelling correction of queries against the vocabulary of the indexed abstracts.
+//!
+//! The text matching levels of `/api/search` only match whole tokens (or their prefixes), so
+//! a single typo, e.g. "qudrant", drops the query to pure semantic ranking. Misspelled tokens
+//! are corrected with the symmetric delete algorithm (SymSpell): every vocabulary word is
+//! indexed by the strings obtained by deleting up to `MAX_DISTANCE` of its characters, and a
+//! token's candidates are the words sharing a deletion with it.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::BufRead;
+use std::ops::{Bound, Range};
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::common::SITE_DATA;
+
+const MAX_DISTANCE: usize = 2;
+/// Shorter tokens are never corrected, they are too ambiguous
+const MIN_TOKEN_LEN: usize = 4;
+/// Tokens at least this long may be corrected by `MAX_DISTANCE` edits, shorter ones by one
+const LONG_TOKEN_LEN: usize = 8;
+
+pub fn get_vocabulary_path() -> String {
+    std::env::var("SPELLING_VOCABULARY_PATH").unwrap_or_else(|_| SITE_DATA.to_string())
+}
+
+/// Lowercase alphanumeric tokens of `text` with their byte ranges.
+fn tokens(text: &str) -> impl Iterator<Item = (Range<usize>, String)> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(move |token| {
+            let start = token.as_ptr() as usize - text.as_ptr() as usize;
+            (start..start + token.len(), token.to_lowercase())
+        })
+}
+
+/// All strings obtained by deleting up to `distance` characters of `word`, including itself.
+fn deletes(word: &str, distance: usize) -> HashSet<String> {
+    let mut result = HashSet::from([word.to_string()]);
+    let mut frontier = vec![word.to_string()];
+    for _ in 0..distance {
+        let mut next = vec![];
+        for word in &frontier {
+            for (i, c) in word.char_indices() {
+                let deleted = format!("{}{}", &word[..i], &word[i + c.len_utf8()..]);
+                if result.insert(deleted.clone()) {
+                    next.push(deleted);
+                }
+            }
+        }
+        frontier = next;
+    }
+    result
+}
+
+/// Optimal string alignment distance: edits, where swapping adjacent characters is one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+fn max_distance(token: &str) -> usize {
+    match token.chars().count() {
+        len if len < MIN_TOKEN_LEN => 0,
+        len if len < LONG_TOKEN_LEN => 1,
+        _ => MAX_DISTANCE,
+    }
+}
+
+/// Indexed words with their number of occurrences.
+pub struct Speller {
+    words: BTreeMap<String, u64>,
+    deletes: HashMap<String, Vec<String>>,
+    /// Hash of the words and their counts
+    version: u64,
+    /// Modification time of the vocabulary file, if read from one
+    modified: Option<SystemTime>,
+}
+
+impl Speller {
+    /// Index the tokens of `texts` that occur at least `min_count` times.
+    pub fn new<'a>(texts: impl IntoIterator<Item = &'a str>, min_count: u64) -> Self {
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        for text in texts {
+            for (_, token) in tokens(text) {
+                *counts.entry(token).or_default() += 1;
+            }
+        }
+        let words: BTreeMap<String, u64> = counts
+            .into_iter()
+            .filter(|(_, count)| *count >= min_count)
+            .collect();
+        let mut index: HashMap<String, Vec<String>> = HashMap::new();
+        for word in words.keys() {
+            // the shortest correctable tokens are one insertion away
+            if word.chars().count() < MIN_TOKEN_LEN - 1 {
+                continue;
+            }
+            for deleted in deletes(word, MAX_DISTANCE) {
+                index.entry(deleted).or_default().push(word.clone());
+            }
+        }
+        let mut hasher = DefaultHasher::new();
+        words.hash(&mut hasher);
+        Self {
+            version: hasher.finish(),
+            words,
+            deletes: index,
+            modified: None,
+        }
+    }
+
+    /// Read the `text` field of every line of the JSON lines file at `path`.
+    pub fn open(path: impl AsRef<Path>, min_count: u64) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let modified = file.metadata()?.modified().ok();
+        let file = std::io::BufReader::new(file);
+        let mut texts = vec![];
+        for line in file.lines() {
+            let record: serde_json::Value = serde_json::from_str(&line?)?;
+            if let Some(text) = record.get("text").and_then(|text| text.as_str()) {
+                texts.push(text.to_string());
+            }
+        }
+        Ok(Self {
+            modified,
+            ..Self::new(texts.iter().map(String::as_str), min_count)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Identifies the vocabulary, corrections only change with it.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Whether the token matches as is, the text index of the site matches prefixes of words.
+    fn is_known(&self, token: &str) -> bool {
+        self.words
+            .range::<str, _>((Bound::Included(token), Bound::Unbounded))
+            .next()
+            .is_some_and(|(word, _)| word.starts_with(token))
+    }
+
+    /// The closest and then most frequent word for an unknown `token`.
+    fn correct_token(&self, token: &str) -> Option<&str> {
+        let max = max_distance(token);
+        if max == 0 || token.chars().any(|c| c.is_numeric()) || self.is_known(token) {
+            return None;
+        }
+        let mut candidates = HashSet::new();
+        for deleted in deletes(token, max) {
+            candidates.extend(self.deletes.get(&deleted).into_iter().flatten());
+        }
+        candidates
+            .into_iter()
+            .filter_map(|word| {
+                let distance = edit_distance(token, word);
+                (distance <= max).then(|| (distance, std::cmp::Reverse(self.words[word]), word))
+            })
+            .min()
+            .map(|(_, _, word)| word.as_str())
+    }
+
+    /// `query` with its misspelled tokens replaced, if any.
+    pub fn correct(&self, query: &str) -> Option<String> {
+        let mut corrected = String::new();
+        let mut end = 0;
+        for (range, token) in tokens(query) {
+            if let Some(word) = self.correct_token(&token) {
+                corrected.push_str(&query[end..range.start]);
+                corrected.push_str(word);
+                end = range.end;
+            }
+        }
+        if end == 0 {
+            return None;
+        }
+        corrected.push_str(&query[end..]);
+        Some(corrected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speller() -> Speller {
+        Speller::new(
+            [
+                "Qdrant is a vector database",
+                "Filter points in a collection with Qdrant",
+                "Create a collection and filter by payload",
+                "Payload filters",
+            ],
+            1,
+        )
+    }
+
+    #[test]
+    fn tokens_keep_their_position() {
+        let text = "Vector-DB, qdrant!";
+        let tokens: Vec<_> = tokens(text).collect();
+        assert_eq!(tokens[1], (7..9, "db".to_string()));
+        assert_eq!(&text[tokens[2].0.clone()], "qdrant");
+    }
+
+    #[test]
+    fn distances_count_swaps_as_one_edit() {
+        assert_eq!(edit_distance("qudrant", "qdrant"), 1);
+        assert_eq!(edit_distance("qdarnt", "qdrant"), 1);
+        assert_eq!(edit_distance("colection", "collection"), 1);
+        assert_eq!(edit_distance("filtr", "filter"), 1);
+        assert_eq!(edit_distance("paylaod", "payload"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn misspelled_tokens_are_corrected() {
+        let speller = speller();
+        assert_eq!(speller.correct("qudrant").as_deref(), Some("qdrant"));
+        assert_eq!(
+            speller.correct("Filtr the colection").as_deref(),
+            Some("filter the collection")
+        );
+        assert_eq!(
+            speller.correct("paylod filtering").as_deref(),
+            Some("payload filtering")
+        );
+    }
+
+    #[test]
+    fn known_words_and_prefixes_are_kept() {
+        let speller = speller();
+        assert_eq!(speller.correct("qdrant collection"), None);
+        // prefixes match while typing
+        assert_eq!(speller.correct("colle"), None);
+        // too short or without a close word
+        assert_eq!(speller.correct("xq"), None);
+        assert_eq!(speller.correct("kubernetes"), None);
+    }
+
+    #[test]
+    fn version_identifies_the_vocabulary() {
+        assert_eq!(speller().version(), speller().version());
+        assert_ne!(speller().version(), Speller::new(["qdrant"], 1).version());
+    }
+
+    #[test]
+    fn rare_words_are_not_indexed() {
+        let speller = Speller::new(["qdrant qdrant typpo"], 2);
+        assert_eq!(speller.len(), 1);
+        assert_eq!(speller.correct("typo"), None);
+    }
+}
@@ -0,0 +1,363 @@
+//! Synonym and acronym expansion of search queries.
+//!
+//! The dictionary is a text file with one group of equivalent terms per line, separated by
+//! commas, e.g. `hnsw, hierarchical navigable small world`. Lines starting with `#` are
+//! comments. Terms are matched on whole words, ignoring case and punctuation, and a query
+//! is expanded to the variants with its terms replaced by the other terms of their groups.
+
+// Allow unused code, as `expand_synonyms` only uses the dictionary
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const SYNONYMS_PATH: &str = "synonyms.txt";
+
+pub fn get_synonyms_path() -> String {
+    std::env::var("SYNONYMS_PATH").unwrap_or_else(|_| SYNONYMS_PATH.to_string())
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Groups of equivalent terms, each term a sequence of words.
+#[derive(Debug, Default)]
+pub struct Synonyms {
+    groups: Vec<Vec<Vec<String>>>,
+    /// Group of every term
+    terms: HashMap<Vec<String>, usize>,
+    /// Words of the longest term
+    max_term_len: usize,
+}
+
+/// A part of a query: a word without synonyms, or a term with the alternatives of its group.
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Word(String),
+    Term {
+        term: Vec<String>,
+        group: &'a [Vec<String>],
+    },
+}
+
+impl Synonyms {
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let mut synonyms = Self::default();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let group: Vec<Vec<String>> = line
+                .split(',')
+                .map(words)
+                .filter(|term| !term.is_empty())
+                .collect();
+            if group.len() < 2 {
+                anyhow::bail!("Line {}: a group needs at least two terms", number + 1);
+            }
+            for term in &group {
+                if synonyms.terms.contains_key(term) {
+                    anyhow::bail!("Line {}: `{}` is in two groups", number + 1, term.join(" "));
+                }
+                synonyms.terms.insert(term.clone(), synonyms.groups.len());
+                synonyms.max_term_len = synonyms.max_term_len.max(term.len());
+            }
+            synonyms.groups.push(group);
+        }
+        Ok(synonyms)
+    }
+
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Split `query` into words and terms of the dictionary, preferring the longest term.
+    fn segments(&self, query: &str) -> Vec<Segment<'_>> {
+        let words = words(query);
+        let mut segments = vec![];
+        let mut start = 0;
+        while start < words.len() {
+            let longest = (1..=self.max_term_len.min(words.len() - start))
+                .rev()
+                .find_map(|len| {
+                    let term = &words[start..start + len];
+                    let group = self.terms.get(term)?;
+                    Some((term.to_vec(), &self.groups[*group][..]))
+                });
+            match longest {
+                Some((term, group)) => {
+                    start += term.len();
+                    segments.push(Segment::Term { term, group });
+                }
+                None => {
+                    segments.push(Segment::Word(words[start].clone()));
+                    start += 1;
+                }
+            }
+        }
+        segments
+    }
+
+    /// Terms of `query` found in the dictionary, with their synonyms.
+    pub fn matches(&self, query: &str) -> Vec<(String, Vec<String>)> {
+        self.segments(query)
+            .into_iter()
+            .filter_map(|segment| match segment {
+                Segment::Word(_) => None,
+                Segment::Term { term, group } => {
+                    let synonyms = group
+                        .iter()
+                        .filter(|other| **other != term)
+                        .map(|other| other.join(" "))
+                        .collect();
+                    Some((term.join(" "), synonyms))
+                }
+            })
+            .collect()
+    }
+
+    /// Variants of `query` with its terms replaced by synonyms, at most `limit`.
+    ///
+    /// The query itself isn't included. Variants are lowercase and without punctuation,
+    /// which doesn't matter for full-text matching or embedding.
+    pub fn expand(&self, query: &str, limit: usize) -> Vec<String> {
+        let segments = self.segments(query);
+        if segments.iter().all(|s| matches!(s, Segment::Word(_))) {
+            return vec![];
+        }
+        // the first variant is the query itself, as every term comes first among its
+        // alternatives
+        let mut variants: Vec<Vec<&str>> = vec![vec![]];
+        for segment in &segments {
+            let alternatives: Vec<&[String]> = match segment {
+                Segment::Word(word) => vec![std::slice::from_ref(word)],
+                Segment::Term { term, group } => std::iter::once(&term[..])
+                    .chain(group.iter().filter(|o| *o != term).map(|o| &o[..]))
+                    .collect(),
+            };
+            variants = variants
+                .iter()
+                .flat_map(|variant| {
+                    alternatives.iter().map(move |alternative| {
+                        let mut variant = variant.clone();
+                        variant.extend(alternative.iter().map(String::as_str));
+                        variant
+                    })
+                })
+                .take(limit + 1)
+                .collect();
+        }
+        variants
+            .into_iter()
+            .skip(1)
+            .map(|variant| variant.join(" "))
+            .collect()
+    }
+}
+
+/// How the synonyms are used for the embedding search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SynonymEmbedding {
+    /// Only the query is embedded
+    None,
+    /// The embeddings of the query and its variants are averaged
+    Average,
+    /// The query and its variants are searched separately and fused by reciprocal rank
+    Multi,
+}
+
+impl SynonymEmbedding {
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("SYNONYMS_EMBEDDING").as_deref() {
+            Err(_) | Ok("none") => Ok(Self::None),
+            Ok("average") => Ok(Self::Average),
+            Ok("multi") => Ok(Self::Multi),
+            Ok(other) => anyhow::bail!("Unknown synonym embedding mode `{other}`"),
+        }
+    }
+}
+
+struct Loaded {
+    synonyms: Arc<Synonyms>,
+    /// Modification time of the loaded file, `None` if there is none
+    modified: Option<SystemTime>,
+    checked_at: Instant,
+}
+
+/// The synonym dictionary, reloaded when its file changes.
+pub struct SynonymStore {
+    path: PathBuf,
+    /// `SYNONYMS_RELOAD_SECS`
+    refresh: Duration,
+    /// Variants per query (`SYNONYMS_MAX_VARIANTS`)
+    pub max_variants: usize,
+    pub embedding: SynonymEmbedding,
+    loaded: Mutex<Loaded>,
+}
+
+impl SynonymStore {
+    pub fn new(path: impl Into<PathBuf>, refresh: Duration) -> Self {
+        let store = Self {
+            path: path.into(),
+            refresh,
+            max_variants: 4,
+            embedding: SynonymEmbedding::None,
+            loaded: Mutex::new(Loaded {
+                synonyms: Arc::default(),
+                modified: None,
+                checked_at: Instant::now(),
+            }),
+        };
+        store.reload(&mut store.loaded.lock().unwrap());
+        store
+    }
+
+    pub fn from_env() -> anyhow::Result<Self> {
+        let refresh = std::env::var("SYNONYMS_RELOAD_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10);
+        let mut store = Self::new(get_synonyms_path(), Duration::from_secs(refresh));
+        store.max_variants = std::env::var("SYNONYMS_MAX_VARIANTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(4);
+        store.embedding = SynonymEmbedding::from_env()?;
+        Ok(store)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    /// Load the file if it changed, keeping the current dictionary if it is invalid.
+    fn reload(&self, loaded: &mut Loaded) {
+        loaded.checked_at = Instant::now();
+        let modified = self.modified();
+        if modified == loaded.modified {
+            return;
+        }
+        loaded.modified = modified;
+        if modified.is_none() {
+            log::info!("No synonyms at {}", self.path.display());
+            loaded.synonyms = Arc::default();
+            return;
+        }
+        let parsed = std::fs::read_to_string(&self.path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Synonyms::parse(&content));
+        match parsed {
+            Ok(synonyms) => {
+                log::info!(
+                    "Loaded {} synonym groups from {}",
+                    synonyms.len(),
+                    self.path.display()
+                );
+                loaded.synonyms = Arc::new(synonyms);
+            }
+            Err(err) => log::warn!(
+                "Keeping the previous synonyms, {}: {err}",
+                self.path.display()
+            ),
+        }
+    }
+
+    /// Modification time of the current dictionary's file, which changes with every reload.
+    pub fn version(&self) -> Option<SystemTime> {
+        self.get();
+        self.loaded.lock().unwrap().modified
+    }
+
+    /// The current dictionary, checking the file at most once per refresh interval.
+    pub fn get(&self) -> Arc<Synonyms> {
+        let mut loaded = self.loaded.lock().unwrap();
+        if loaded.checked_at.elapsed() >= self.refresh {
+            self.reload(&mut loaded);
+        }
+        loaded.synonyms.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DICTIONARY: &str = "# acronyms\n\
+                              ann, approximate nearest neighbor\n\
+                              k8s, kubernetes\n\
+                              \n\
+                              vector db, vector database, vector search engine\n";
+
+    #[test]
+    fn groups_are_parsed() {
+        let synonyms = Synonyms::parse(DICTIONARY).unwrap();
+        assert_eq!(synonyms.len(), 3);
+        assert_eq!(synonyms.max_term_len, 3);
+        assert!(Synonyms::parse("hnsw").is_err());
+        assert!(Synonyms::parse("ann, knn\nANN, vector search").is_err());
+    }
+
+    #[test]
+    fn longest_terms_are_matched() {
+        let synonyms = Synonyms::parse("vector, embedding\nvector db, vector database").unwrap();
+        assert_eq!(
+            synonyms.matches("Best vector DB?"),
+            vec![("vector db".to_string(), vec!["vector database".to_string()])]
+        );
+    }
+
+    #[test]
+    fn queries_are_expanded() {
+        let synonyms = Synonyms::parse(DICTIONARY).unwrap();
+        assert_eq!(
+            synonyms.expand("ANN on k8s", 10),
+            vec![
+                "ann on kubernetes",
+                "approximate nearest neighbor on k8s",
+                "approximate nearest neighbor on kubernetes",
+            ]
+        );
+        assert_eq!(synonyms.expand("ANN on k8s", 2).len(), 2);
+        assert!(synonyms.expand("payload index", 10).is_empty());
+    }
+
+    #[test]
+    fn changed_files_are_reloaded() {
+        let path = std::env::temp_dir().join(format!("synonyms-{}.txt", std::process::id()));
+        std::fs::write(&path, "k8s, kubernetes\n").unwrap();
+        let store = SynonymStore::new(&path, Duration::ZERO);
+        assert_eq!(store.get().len(), 1);
+
+        // invalid dictionaries are ignored
+        std::fs::write(&path, "k8s\n").unwrap();
+        let modified = SystemTime::now() + Duration::from_secs(1);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(store.get().len(), 1);
+
+        std::fs::write(&path, "k8s, kubernetes\nann, knn\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(store.get().len(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(store.get().len(), 0);
+    }
+}
@@ -0,0 +1,211 @@
+//! Validation of the site data before it is indexed.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use serde_json::Value;
+
+/// Number of examples listed per kind of finding.
+const EXAMPLES: usize = 10;
+
+/// Findings of one kind, with the line numbers of the first few occurrences.
+#[derive(Debug, Default)]
+pub struct Findings {
+    pub count: usize,
+    pub examples: Vec<(usize, String)>,
+}
+
+impl Findings {
+    fn add(&mut self, line: usize, message: impl Into<String>) {
+        self.count += 1;
+        if self.examples.len() < EXAMPLES {
+            self.examples.push((line, message.into()));
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub records: usize,
+    /// Lines that are not a JSON object
+    pub malformed: Findings,
+    /// Records with missing fields or fields of the wrong type
+    pub schema: Findings,
+    /// Records with a blank `text`
+    pub empty_texts: Findings,
+    /// Records with the same `url` and `text` as an earlier one
+    pub duplicates: Findings,
+    /// Records whose `text` is truncated when embedded
+    pub over_length: Findings,
+    pub tags: BTreeMap<String, usize>,
+}
+
+impl ValidationReport {
+    /// Violations make the data unfit for indexing.
+    pub fn violations(&self) -> usize {
+        self.malformed.count + self.schema.count + self.empty_texts.count
+    }
+
+    /// Warnings point to wasted or degraded points, but don't break the index.
+    pub fn warnings(&self) -> usize {
+        self.duplicates.count + self.over_length.count
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} records", self.records)?;
+        for (name, findings) in [
+            ("malformed lines", &self.malformed),
+            ("schema violations", &self.schema),
+            ("empty texts", &self.empty_texts),
+            ("duplicates", &self.duplicates),
+            ("over-length texts", &self.over_length),
+        ] {
+            writeln!(f, "{}: {}", name, findings.count)?;
+            for (line, message) in &findings.examples {
+                writeln!(f, "  line {}: {}", line, message)?;
+            }
+            if findings.count > findings.examples.len() {
+                writeln!(f, "  ...")?;
+            }
+        }
+        writeln!(f, "tags:")?;
+        let mut tags: Vec<_> = self.tags.iter().collect();
+        tags.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (tag, count) in tags {
+            writeln!(f, "  {}: {}", tag, count)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_string(record: &Value, field: &str, required: bool) -> Result<(), String> {
+    match record.get(field) {
+        Some(Value::String(_)) => Ok(()),
+        None | Some(Value::Null) if !required => Ok(()),
+        None | Some(Value::Null) => Err(format!("missing `{field}`")),
+        Some(other) => Err(format!("`{field}` is not a string: {other}")),
+    }
+}
+
+fn check_string_list(record: &Value, field: &str) -> Result<(), String> {
+    match record.get(field) {
+        None | Some(Value::Null) => Ok(()),
+        Some(Value::Array(items)) if items.iter().all(Value::is_string) => Ok(()),
+        Some(other) => Err(format!("`{field}` is not a list of strings: {other}")),
+    }
+}
+
+/// Check the types of the fields used by the search service.
+pub fn check_schema(record: &Value) -> Result<(), String> {
+    if !record.is_object() {
+        return Err("not a JSON object".to_string());
+    }
+    check_string(record, "text", true)?;
+    check_string(record, "url", true)?;
+    check_string(record, "tag", true)?;
+    check_string_list(record, "sections")?;
+    check_string(record, "partition", false)?;
+    Ok(())
+}
+
+/// Validate JSONL `lines` of the site data.
+///
+/// `token_count` returns the number of model tokens of a text, including special tokens,
+/// which is compared against `max_tokens`.
+pub fn validate<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    token_count: impl Fn(&str) -> usize,
+    max_tokens: usize,
+) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let mut seen = HashSet::new();
+    for (idx, line) in lines.enumerate() {
+        let line_no = idx + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        report.records += 1;
+        let record: Value = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(err) => {
+                report
+                    .malformed
+                    .add(line_no, format!("invalid JSON: {err}"));
+                continue;
+            }
+        };
+        if let Err(err) = check_schema(&record) {
+            report.schema.add(line_no, err);
+            continue;
+        }
+        let text = record["text"].as_str().unwrap_or_default();
+        let url = record["url"].as_str().unwrap_or_default();
+        let tag = record["tag"].as_str().unwrap_or_default();
+        *report.tags.entry(tag.to_string()).or_default() += 1;
+
+        if text.trim().is_empty() {
+            report.empty_texts.add(line_no, format!("{url} <{tag}>"));
+            continue;
+        }
+        if !seen.insert((url.to_string(), text.to_string())) {
+            report.duplicates.add(line_no, format!("{url}: {text}"));
+        }
+        let tokens = token_count(text);
+        if tokens > max_tokens {
+            report
+                .over_length
+                .add(line_no, format!("{url}: {tokens} tokens"));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+
+    #[test]
+    fn schema_types() {
+        let ok = serde_json::json!({"text": "t", "url": "/u", "tag": "p", "sections": ["a"], "partition": null});
+        assert!(check_schema(&ok).is_ok());
+        let no_url = serde_json::json!({"text": "t", "tag": "p"});
+        assert_eq!(check_schema(&no_url).unwrap_err(), "missing `url`");
+        let bad_sections =
+            serde_json::json!({"text": "t", "url": "/u", "tag": "p", "sections": [1]});
+        assert!(check_schema(&bad_sections).is_err());
+        let bad_partition =
+            serde_json::json!({"text": "t", "url": "/u", "tag": "p", "partition": 3});
+        assert!(check_schema(&bad_partition).is_err());
+    }
+
+    #[test]
+    fn report_findings() {
+        let data = [
+            r#"{"text": "hello world", "url": "/a", "tag": "p"}"#,
+            r#"{"text": "hello world", "url": "/a", "tag": "p"}"#,
+            r#"{"text": " ", "url": "/a", "tag": "h1"}"#,
+            r#"{"text": "one two three four", "url": "/b", "tag": "li"}"#,
+            r#"{"text": 5, "url": "/b", "tag": "li"}"#,
+            r#"not json"#,
+        ]
+        .join("\n");
+        let report = validate(data.lines(), words, 3);
+        assert_eq!(report.records, 6);
+        assert_eq!(report.duplicates.count, 1);
+        assert_eq!(report.duplicates.examples[0].0, 2);
+        assert_eq!(report.empty_texts.count, 1);
+        assert_eq!(report.over_length.count, 1);
+        assert_eq!(report.schema.count, 1);
+        assert_eq!(report.malformed.count, 1);
+        assert_eq!(report.violations(), 3);
+        assert_eq!(report.warnings(), 2);
+        assert_eq!(report.tags["p"], 2);
+        assert_eq!(report.tags["h1"], 1);
+    }
+}
@@ -1,4 +1,9 @@
 import re
+import time
+
+from qdrant_client import QdrantClient
+
+from site_search.config import INDEXED_AT_KEY
 
 
 def limit_text(text: str, lim: int = 80):
@@ -47,3 +52,15 @@ def highlight_search_match(text: str, query: str, before="<b>", after="</b>"):
     """
     # Replace matches only on word boundaries
     return re.compile(r"\b(" + re.escape(query) + ")", re.IGNORECASE).sub(before + '\\1' + after, text)
+
+
+def mark_indexed(qdrant_client: QdrantClient, collection_name: str):
+    """
+    Record the time of this indexing run in the metadata of the collection
+
+    :param qdrant_client: Client of the Qdrant instance holding the collection
+    :param collection_name: Name of the collection that was written
+    """
+    qdrant_client.update_collection(
+        collection_name, metadata={INDEXED_AT_KEY: int(time.time())}
+    )
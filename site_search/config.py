@@ -7,7 +7,10 @@ DATA_DIR = os.path.join(ROOT_DIR, 'data')
 
 COLLECTION_NAME = "site"
 SECTION_COLLECTION_NAME = "sections"
+SECTION_SPARSE_VECTOR = "bm25"
 SNIPPET_COLLECTION_NAME = "snippet-search"
+# collection metadata with the time of the last indexing run, versions the caches of rust_search
+INDEXED_AT_KEY = "indexed_at"
 
 load_dotenv()
 
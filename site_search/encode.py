@@ -7,6 +7,7 @@ from blingfire import text_to_sentences
 from qdrant_client import QdrantClient
 from qdrant_client.http.models import Distance, PayloadSchemaType, VectorParams, TextIndexParams, TokenizerType, PointStruct
 
+from site_search.common import mark_indexed
 from site_search.config import QDRANT_HOST, QDRANT_PORT, COLLECTION_NAME, DATA_DIR, QDRANT_API_KEY
 from site_search.neural_searcher import NeuralSearcher
 
@@ -101,3 +102,5 @@ if __name__ == '__main__':
         batch_size=BATCH_SIZE,
         parallel=2
     )
+
+    mark_indexed(qdrant_client, COLLECTION_NAME)
@@ -14,7 +14,9 @@ from qdrant_client import QdrantClient
 from qdrant_client.http.models import (
     Distance,
     Document,
+    Modifier,
     PointStruct,
+    SparseVectorParams,
     TextIndexParams,
     TextIndexType,
     TokenizerType,
@@ -25,12 +27,14 @@ from usp.fetch_parse import SitemapFetcher
 from usp.objects.sitemap import IndexWebsiteSitemap, InvalidSitemap
 from usp.tree import sitemap_tree_for_homepage
 
+from site_search.common import mark_indexed
 from site_search.config import (
     NEURAL_ENCODER,
     QDRANT_API_KEY,
     QDRANT_HOST,
     QDRANT_PORT,
     SECTION_COLLECTION_NAME,
+    SECTION_SPARSE_VECTOR,
 )
 
 
@@ -68,7 +72,10 @@ class Section(BaseModel):
         return PointStruct(
             id=self.uuid,
             payload=self.metadata,
-            vector=Document(text=self.content, model=model),
+            vector={
+                "": Document(text=self.content, model=model),
+                SECTION_SPARSE_VECTOR: Document(text=self.content, model="qdrant/bm25"),
+            },
         )
 
 
@@ -203,6 +210,9 @@ def main():
             size=qdrant_client.get_embedding_size(NEURAL_ENCODER),
             distance=Distance.COSINE,
         ),
+        sparse_vectors_config={
+            SECTION_SPARSE_VECTOR: SparseVectorParams(modifier=Modifier.IDF),
+        },
     )
 
     qdrant_client.create_payload_index(
@@ -259,6 +269,14 @@ def main():
         wait=True,
     )
 
+    # sections of a page are scrolled in line order
+    qdrant_client.create_payload_index(
+        collection_name=SECTION_COLLECTION_NAME,
+        field_name="line",
+        field_schema=PayloadSchemaType.INTEGER,
+        wait=True,
+    )
+
     urls = _all_sitemap_urls("https://qdrant.tech/", "https://qdrant.tech/sitemap.xml")
 
     with concurrent.futures.ProcessPoolExecutor(max_workers=10) as pool:
@@ -279,6 +297,8 @@ def main():
                 ],
             )
 
+    mark_indexed(qdrant_client, SECTION_COLLECTION_NAME)
+
 
 if __name__ == "__main__":
     main()
@@ -23,6 +23,7 @@ from usp.fetch_parse import SitemapFetcher
 from usp.objects.sitemap import IndexWebsiteSitemap, InvalidSitemap
 from usp.tree import sitemap_tree_for_homepage
 
+from site_search.common import mark_indexed
 from site_search.config import (
     SNIPPET_ENCODER,
     QDRANT_API_KEY,
@@ -245,6 +246,8 @@ def main():
                 points=[skill.as_point(SNIPPET_ENCODER) for skill in result.skills],
             )
 
+    mark_indexed(qdrant_client, SKILLS_COLLECTION_NAME)
+
 
 if __name__ == "__main__":
     main()